@@ -1,19 +1,43 @@
 use crate::{
-    config::{BlockConfig, FileConfig, ThemeVars},
-    utils::expand_tilde,
+    config::{BlockConfig, FileConfig, SortOrder, ThemeVars},
+    utils::{expand_path, natural_cmp},
 };
 use colored::Colorize;
 use lazy_static::lazy_static;
-use regex::{Regex, RegexBuilder};
+use regex::{Captures, Regex};
 use std::{
     collections::{hash_set::IntoIter, HashSet},
     fs,
+    ops::Range,
     path::PathBuf,
     process::exit,
 };
 
+/// Location of Themer block inside the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSpan {
+    /// Bytes from the start of the start marker line to the end of the end marker line,
+    /// without line break
+    pub range: Range<usize>,
+    /// Whitespace before the start marker
+    pub indent: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Transformations of keys in formats, chained as `<key|kebab|upper>`
+pub const KEY_TRANSFORMS: &[&str] = &["upper", "lower", "snake", "kebab", "camel", "pascal"];
+
+lazy_static! {
+    static ref KEY_RE: Regex = Regex::new(r"<key((?:\|\w+)*)>").unwrap();
+}
+
+type VarsFilter<'a> = Box<dyn FnMut(&(String, String)) -> bool + 'a>;
+
 pub struct BlockGenerator {
     vars: ThemeVars,
+    /// Variables that are booleans or numbers, they aren't quoted by `escape`
+    literals: HashSet<String>,
     theme_name: String,
     pub config: BlockConfig,
 }
@@ -24,6 +48,7 @@ impl BlockGenerator {
             FileConfig::Single(c) => Self {
                 theme_name,
                 vars: Self::apply_aliases(vars, &c.block.aliases),
+                literals: HashSet::new(),
                 config: c,
             },
             FileConfig::Multi(_) => {
@@ -33,38 +58,100 @@ impl BlockGenerator {
         }
     }
 
+    /// Sets variables that are booleans or numbers, aliases are applied to them as well
+    pub fn with_literals(mut self, literals: HashSet<String>) -> Self {
+        let aliases = self.config.block.aliases.clone().unwrap_or_default();
+
+        self.literals = literals
+            .into_iter()
+            .map(
+                |key| match aliases.iter().find(|(_, old_key)| **old_key == key) {
+                    Some((new_key, _)) => new_key.clone(),
+                    None => key,
+                },
+            )
+            .collect();
+        self
+    }
+
     fn apply_aliases(vars: &ThemeVars, aliases: &Option<ThemeVars>) -> ThemeVars {
-        let mut theme = vars.clone();
-
-        if let Some(aliases) = aliases {
-            for (new_key, old_key) in aliases {
-                if theme.contains_key(old_key) {
-                    // Remove old key and add new one to ThemeVars
-                    let val = theme.get(old_key).unwrap().clone();
-                    theme.remove(old_key);
-                    theme.insert(new_key.to_owned(), val.to_owned());
-                } else {
-                    log::warn!("Failed to alias {new_key}: {old_key} does not exist");
-                }
+        let aliases = match aliases {
+            Some(a) => a,
+            None => return vars.clone(),
+        };
+
+        for (new_key, old_key) in aliases {
+            if !vars.contains_key(old_key) {
+                log::warn!("Failed to alias {new_key}: {old_key} does not exist");
             }
         }
-        theme
+
+        // Renames keys in place, so aliased variables keep their position
+        vars.iter()
+            .map(
+                |(key, val)| match aliases.iter().find(|(_, old_key)| *old_key == key) {
+                    Some((new_key, _)) => (new_key.clone(), val.clone()),
+                    None => (key.clone(), val.clone()),
+                },
+            )
+            .collect()
+    }
+
+    /// Checks if the line is the marker, allowing whitespace around it and before closing comment
+    fn is_marker(&self, line: &str, marker: &str) -> bool {
+        let rest = match line
+            .trim()
+            .strip_prefix(self.config.comment())
+            .and_then(|l| l.strip_prefix(' '))
+            .and_then(|l| l.strip_prefix(marker))
+        {
+            Some(r) => r.trim(),
+            None => return false,
+        };
+
+        rest == self.config.closing_comment.as_deref().unwrap_or_default()
     }
 
-    pub fn get_re(&self) -> Regex {
+    /// Finds every block of the current tag, pairing start and end markers line by line
+    pub fn find_blocks(&self, contents: &str) -> Result<Vec<BlockSpan>, String> {
         let (start, end) = self.get_tags();
+        let mut blocks = vec![];
+        // Offset, line and indentation of the start marker of the block that is not closed yet
+        let mut open: Option<(usize, usize, &str)> = None;
+        let mut offset = 0;
+
+        for (i, line) in contents.split_inclusive('\n').enumerate() {
+            let line_number = i + 1;
+            let content = line.trim_end_matches(['\n', '\r']);
+
+            if self.is_marker(content, &start) {
+                if let Some((_, opened, _)) = open {
+                    return Err(format!(
+                        "Nested marker `{start}` at line {line_number} (block is opened at line {opened})"
+                    ));
+                }
+                let indent = &content[..content.len() - content.trim_start().len()];
+                open = Some((offset, line_number, indent));
+            } else if self.is_marker(content, &end) {
+                match open.take() {
+                    Some((start_offset, start_line, indent)) => blocks.push(BlockSpan {
+                        range: start_offset..offset + content.len(),
+                        indent: indent.to_owned(),
+                        start_line,
+                        end_line: line_number,
+                    }),
+                    None => return Err(format!("Unmatched marker `{end}` at line {line_number}")),
+                }
+            }
 
-        let regex_str = format!(
-            "{0} {start}[ \t]*{1}\n.*{0} {end}[ \t]*{1}",
-            regex::escape(&self.config.comment),
-            regex::escape(&self.config.closing_comment.clone().unwrap_or_default())
-        );
-        log::debug!("Generated regex: {}", regex_str);
+            offset += line.len();
+        }
 
-        RegexBuilder::new(&regex_str)
-            .dot_matches_new_line(true)
-            .build()
-            .unwrap()
+        if let Some((_, line, _)) = open {
+            return Err(format!("Unmatched marker `{start}` at line {line}"));
+        }
+
+        Ok(blocks)
     }
 
     pub fn generate(&self) -> String {
@@ -74,21 +161,29 @@ impl BlockGenerator {
         }
     }
 
+    /// Names of start and end markers of the block. Tag is placed instead of `{tag}`, or appended
+    /// after a colon if marker has no placeholder (`THEMER:tag` and `THEMER_END:tag`)
     pub fn get_tags(&self) -> (String, String) {
-        let mut block_name = String::from("THEMER");
-        if let Some(tag) = &self.config.tag {
-            block_name.push(':');
-            block_name.push_str(tag);
-        }
-        let mut end_name = block_name.clone();
-        // 5 is the length of the word "THEMeR", after which we should put "_END" so it becomes
-        // "THEMER_END"
-        end_name.insert_str(6, "_END");
+        let expand = |marker: &str| match &self.config.tag {
+            Some(tag) if marker.contains("{tag}") => marker.replace("{tag}", tag),
+            Some(tag) => format!("{marker}:{tag}"),
+            None => marker.replace("{tag}", "").trim().to_owned(),
+        };
 
-        (block_name, end_name)
+        (
+            expand(&self.config.start_marker),
+            expand(&self.config.end_marker),
+        )
     }
+
     /// Wraps contents with appropriate comments that will identify Themer block
-    pub fn wrap(&self, contents: &String) -> String {
+    pub fn wrap(&self, contents: &str) -> String {
+        self.wrap_indented(contents, "")
+    }
+
+    /// Wraps contents placing markers after `indent`. Every non-empty line of contents is
+    /// indented the same way, unless the `indent` option overrides it
+    pub fn wrap_indented(&self, contents: &str, indent: &str) -> String {
         let (start, end) = self.get_tags();
 
         let mut closing = self.config.closing_comment.clone().unwrap_or_default();
@@ -98,52 +193,174 @@ impl BlockGenerator {
             closing.insert(0, ' ');
         }
 
+        let contents_indent = match &self.config.block.indent {
+            Some(i) => i.text(),
+            None => indent.to_owned(),
+        };
+        let contents = contents
+            .split('\n')
+            .map(|line| match line.is_empty() {
+                true => line.to_owned(),
+                false => format!("{contents_indent}{line}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
         format!(
-            "{0} {start}{1}\n{contents}\n{0} {end}{1}",
-            &self.config.comment, closing
+            "{indent}{0} {start}{1}\n{contents}\n{indent}{0} {end}{1}",
+            self.config.comment(),
+            closing
         )
     }
 
     fn default_block(&self) -> String {
         let mut block = String::new();
 
-        let mut filter_closure: Option<Box<dyn FnMut(&(String, String)) -> bool>> = None;
+        let mut filter_closure: Option<VarsFilter> = None;
 
         // `only` has more "power" than `ignore`, so here we decide how to filter variables
         if !self.config.block.only.is_empty() {
-            filter_closure = Some(Box::new(|x| self.config.block.only.contains(&x.0)));
+            filter_closure = Some(Box::new(|x| Self::listed(&self.config.block.only, &x.0)));
         } else if !self.config.block.ignore.is_empty() {
-            filter_closure = Some(Box::new(|x| !self.config.block.ignore.contains(&x.0)));
+            filter_closure = Some(Box::new(|x| !Self::listed(&self.config.block.ignore, &x.0)));
         }
 
         // Filters variables if needed, otherwise leaving everything as it was
-        let vars = self
+        let mut vars: Vec<(String, String)> = self
             .vars
             .clone()
             .into_iter()
-            .filter(filter_closure.unwrap_or(Box::new(|_| true)));
+            .filter(filter_closure.unwrap_or(Box::new(|_| true)))
+            .collect();
+
+        match self.config.block.sort {
+            SortOrder::Declared => (),
+            SortOrder::Alpha => vars.sort_by(|a, b| a.0.cmp(&b.0)),
+            SortOrder::Natural => vars.sort_by(|a, b| natural_cmp(&a.0, &b.0)),
+        }
+
+        let opts = &self.config.block;
+        for format in std::iter::once(&opts.format).chain(opts.formats.values()) {
+            for unknown in Self::unknown_transforms(format) {
+                log::warn!(
+                    "Format of file `{}`: unknown key transformation `{unknown}`",
+                    self.config.path
+                );
+            }
+        }
 
         for (key, val) in vars {
-            block.push_str(
-                &self
-                    .config
-                    .block
-                    .format
-                    .clone()
-                    .replace("<key>", &key)
-                    .replace("<value>", &val),
-            );
+            // The most specific entry wins: the key itself, then the closest group
+            let format = opts
+                .formats
+                .iter()
+                .filter(|(k, _)| Self::listed(std::slice::from_ref(k), &key))
+                .max_by_key(|(k, _)| k.len())
+                .map(|(_, f)| f);
+            let value = self.escape(&key, &val);
+            let key = key.replace('.', &opts.separator);
+
+            let line = KEY_RE.replace_all(format.unwrap_or(&opts.format), |caps: &Captures| {
+                format!("{}{}", opts.key_prefix, self.transform_key(&key, &caps[1]))
+            });
+            block.push_str(&line.replace("<value>", &value));
             block.push('\n');
         }
 
         block.trim_end().to_owned()
     }
 
-    pub fn custom_block(&self, mut input: String, depth: u8) -> String {
-        input = self.expand_vars(input);
-        input = self.resolve_imports(input, depth);
+    fn escape(&self, key: &str, value: &str) -> String {
+        self.config
+            .block
+            .escape
+            .apply(value, self.literals.contains(key))
+    }
+
+    /// Transformations used by the format that are not in `KEY_TRANSFORMS`
+    pub fn unknown_transforms(format: &str) -> Vec<String> {
+        KEY_RE
+            .captures_iter(format)
+            .flat_map(|caps| {
+                caps[1]
+                    .split('|')
+                    .filter(|t| !t.is_empty() && !KEY_TRANSFORMS.contains(t))
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
 
-        return input.trim_end().to_owned();
+    /// Applies chain of transformations (`|kebab|upper`) to the key
+    fn transform_key(&self, key: &str, transforms: &str) -> String {
+        let mut key = key.to_owned();
+
+        for transform in transforms.split('|').filter(|t| !t.is_empty()) {
+            let words = Self::words(&key);
+            let capitalize = |w: &String| {
+                let mut chars = w.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default()
+            };
+
+            key = match transform {
+                "upper" => key.to_uppercase(),
+                "lower" => key.to_lowercase(),
+                "snake" => words.join("_"),
+                "kebab" => words.join("-"),
+                "camel" => words
+                    .iter()
+                    .enumerate()
+                    .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                    .collect(),
+                "pascal" => words.iter().map(capitalize).collect(),
+                // Reported once per block by `default_block`
+                _ => key,
+            };
+        }
+
+        key
+    }
+
+    /// Lowercase words of the key, split by punctuation and camel case humps
+    fn words(key: &str) -> Vec<String> {
+        let mut words = vec![];
+        let mut word = String::new();
+        let mut prev_lower = false;
+
+        for c in key.chars() {
+            let boundary = !c.is_alphanumeric() || (c.is_uppercase() && prev_lower);
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            if c.is_alphanumeric() {
+                word.extend(c.to_lowercase());
+            }
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+
+        words
+    }
+
+    /// Checks if the key or the group it's nested in is present in the list
+    fn listed(list: &[String], key: &str) -> bool {
+        list.iter()
+            .any(|k| k == key || key.starts_with(&format!("{k}.")))
+    }
+
+    pub fn custom_block(&self, input: String, depth: u8) -> String {
+        self.expand(input, depth).trim_end().to_owned()
+    }
+
+    /// Puts values of variables and imports in place, keeping the whitespace around
+    pub fn expand(&self, mut input: String, depth: u8) -> String {
+        input = self.expand_vars(input);
+        self.resolve_imports(input, depth)
     }
 
     /// Turns one-word variables into actual values
@@ -156,7 +373,7 @@ impl BlockGenerator {
                     let var_name = var.replace("<", "").replace(">", "");
 
                     if let Some(v) = self.vars.get(&var_name) {
-                        input = input.replace(var, v);
+                        input = input.replace(var, &self.escape(&var_name, v));
                     } else {
                         log::warn!(
                             "Custom block for file `{}`: variable {var} cannot be found.",
@@ -184,7 +401,13 @@ impl BlockGenerator {
             let path = match import.split(" ").nth(1) {
                 Some(v) => {
                     log::debug!("Importing {v:#?}");
-                    PathBuf::from(expand_tilde(&v.to_string()))
+                    match expand_path(v) {
+                        Ok(p) => PathBuf::from(p),
+                        Err(e) => {
+                            log::error!("Failed to resolve import `{import}`: {e}");
+                            exit(1);
+                        }
+                    }
                 }
                 None => {
                     log::error!("`{import}` is not valid.");
@@ -209,14 +432,14 @@ impl BlockGenerator {
     }
 
     /// A generic function to retrive unique substrings from string with Regex
-    fn find_with_re(contents: &String, re: &Regex) -> IntoIter<String> {
+    fn find_with_re(contents: &str, re: &Regex) -> IntoIter<String> {
         re.find_iter(contents)
             .map(|x| x.as_str().to_string())
             .collect::<HashSet<String>>()
             .into_iter()
     }
     /// Finds unique variables inside contents
-    fn extract_vars(contents: &String) -> Vec<String> {
+    pub fn extract_vars(contents: &str) -> Vec<String> {
         lazy_static! {
             // Matches only single word tokens: no variables inside variables
             static ref RE: Regex = Regex::new("<\\S+[^<>]>").unwrap();
@@ -226,7 +449,7 @@ impl BlockGenerator {
     }
 
     /// Finds unique imports inside contents
-    fn extract_imports(contents: &String) -> Vec<String> {
+    fn extract_imports(contents: &str) -> Vec<String> {
         lazy_static! {
             // Matches only single word tokens: no variables inside variables
             static ref RE: Regex = Regex::new("<import .*>").unwrap();
@@ -241,17 +464,20 @@ impl BlockGenerator {
 #[cfg(test)]
 mod tests {
     use super::BlockGenerator;
-    use crate::config::{Config, FileConfig, ThemeVars};
+    use crate::config::{BlockConfig, Config, FileConfig, ThemeVars};
     use std::fs;
 
     fn load_config(file: &'static str) -> (ThemeVars, FileConfig) {
-        let conf: Config = serde_yaml::from_str(
-            &fs::read_to_string(format!("./test-configs/config.yml")).unwrap(),
-        )
-        .unwrap();
+        load_theme_config("theme", file)
+    }
+
+    fn load_theme_config(theme: &'static str, file: &'static str) -> (ThemeVars, FileConfig) {
+        let conf: Config =
+            serde_yaml::from_str(&fs::read_to_string("./test-configs/config.yml").unwrap())
+                .unwrap();
 
         (
-            conf.themes.get("theme").unwrap().to_owned(),
+            conf.vars(theme).unwrap(),
             conf.files.get(file).unwrap().to_owned(),
         )
     }
@@ -346,6 +572,154 @@ foreground = #ffffff
         );
     }
 
+    #[test]
+    fn nested() {
+        let (theme, conf) = load_theme_config("structured", "nested");
+        let res = BlockGenerator::new("structured".to_string(), &theme, conf).generate();
+
+        assert_eq!(res, "set $ui-border 2\nset $ui-gap 8\nborder 2");
+    }
+
+    #[test]
+    fn sort() {
+        let (theme, conf) = load_theme_config("terminal", "declared");
+        let res = BlockGenerator::new("terminal".to_string(), &theme, conf).generate();
+        assert_eq!(res, "color2 = #222222\ncolor10 = #aaaaaa\ncolor1 = #111111");
+
+        let (theme, conf) = load_theme_config("terminal", "alpha");
+        let res = BlockGenerator::new("terminal".to_string(), &theme, conf).generate();
+        assert_eq!(res, "color1 = #111111\ncolor10 = #aaaaaa\ncolor2 = #222222");
+
+        let (theme, conf) = load_theme_config("terminal", "natural");
+        let res = BlockGenerator::new("terminal".to_string(), &theme, conf).generate();
+        assert_eq!(res, "color1 = #111111\ncolor2 = #222222\ncolor10 = #aaaaaa");
+    }
+
+    #[test]
+    fn markers() {
+        let (theme, conf) = load_config("markers");
+        let mut blocks = conf.flatten().into_iter();
+
+        let mut blk = BlockGenerator::new(
+            "theme".to_string(),
+            &theme,
+            FileConfig::Single(blocks.next().unwrap()),
+        );
+        assert_eq!(
+            blk.wrap("contents"),
+            "# BEGIN colors\ncontents\n# END colors"
+        );
+        assert_eq!(
+            blk.find_blocks("# BEGIN colors\nold\n# END colors")
+                .unwrap()[0]
+                .range,
+            0..31
+        );
+
+        blk.config.tag = None;
+        assert_eq!(blk.get_tags(), ("BEGIN".to_owned(), "END".to_owned()));
+    }
+
+    #[test]
+    fn scan_markers() {
+        let (theme, conf) = load_config("basic");
+        let blk = BlockGenerator::new("theme".to_string(), &theme, conf);
+
+        let contents = "a\n; THEMER\nold\n; THEMER_END\nb\n  ; THEMER \nold\n  ; THEMER_END\n";
+        let blocks = blk.find_blocks(contents).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].start_line, blocks[0].end_line), (2, 4));
+        assert_eq!(
+            &contents[blocks[0].range.clone()],
+            "; THEMER\nold\n; THEMER_END"
+        );
+        assert_eq!((blocks[1].start_line, blocks[1].end_line), (6, 8));
+        assert_eq!(blocks[1].indent, "  ");
+        assert_eq!(
+            &contents[blocks[1].range.clone()],
+            "  ; THEMER \nold\n  ; THEMER_END"
+        );
+
+        assert_eq!(
+            blk.find_blocks("; THEMER\n; THEMER\n; THEMER_END")
+                .unwrap_err(),
+            "Nested marker `THEMER` at line 2 (block is opened at line 1)"
+        );
+        assert_eq!(
+            blk.find_blocks("; THEMER_END\n").unwrap_err(),
+            "Unmatched marker `THEMER_END` at line 1"
+        );
+        assert_eq!(
+            blk.find_blocks("x\n; THEMER\n").unwrap_err(),
+            "Unmatched marker `THEMER` at line 2"
+        );
+    }
+
+    #[test]
+    fn indentation() {
+        let (theme, conf) = load_config("basic");
+        let mut blk = BlockGenerator::new("theme".to_string(), &theme, conf);
+
+        assert_eq!(
+            blk.wrap_indented("a = 1\n\n  b = 2", "\t"),
+            "\t; THEMER\n\ta = 1\n\n\t  b = 2\n\t; THEMER_END"
+        );
+
+        let (_, conf) = load_config("indented");
+        blk.config = conf.flatten().remove(0);
+        assert_eq!(
+            blk.wrap_indented("a = 1", "  "),
+            "  # THEMER\n    a = 1\n  # THEMER_END"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let (theme, conf) = load_theme_config("structured", "formats");
+        let gen = BlockGenerator::new("structured".to_string(), &theme, conf);
+
+        assert_eq!(
+            gen.generate(),
+            "--background: #000000;\n--uiBorder 2\n--uiGap 8"
+        );
+        assert_eq!(
+            BlockGenerator::words("bgAlt.ui-gap_2"),
+            vec!["bg", "alt", "ui", "gap", "2"]
+        );
+
+        let (theme, conf) = load_config("formats");
+        let gen = BlockGenerator::new("theme".to_string(), &theme, conf);
+        assert_eq!(
+            gen.generate(),
+            "--background: #000000;\n--FOREGROUND=#ffffff"
+        );
+
+        // The closest group wins, whatever the order of entries
+        let mut vars = ThemeVars::new();
+        vars.insert("ui.bar.height".to_owned(), "20".to_owned());
+        let mut config = BlockConfig::default();
+        config.block.format = "<key> <value>".to_owned();
+        config
+            .block
+            .formats
+            .insert("ui".to_owned(), "ui <value>".to_owned());
+        config
+            .block
+            .formats
+            .insert("ui.bar".to_owned(), "bar <value>".to_owned());
+        config
+            .block
+            .formats
+            .insert("ui.b".to_owned(), "b <value>".to_owned());
+        let gen = BlockGenerator::new(String::new(), &vars, FileConfig::Single(config));
+        assert_eq!(gen.generate(), "bar 20");
+
+        assert_eq!(
+            BlockGenerator::unknown_transforms("<key|kebab|Upper> <key|bad>"),
+            vec!["Upper", "bad"]
+        );
+    }
+
     #[test]
     fn tags() {
         let (theme, conf) = load_config("tags");
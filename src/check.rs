@@ -0,0 +1,283 @@
+use crate::{
+    block::BlockGenerator,
+    config::{BlockConfig, Config, ThemeVars},
+};
+use colored::Colorize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+};
+
+/// Whether the key is a variable or a group of nested variables
+fn has_key(vars: &ThemeVars, key: &str) -> bool {
+    let group = format!("{key}.");
+    vars.keys().any(|k| k == key || k.starts_with(&group))
+}
+
+/// Verifies themes against `schema` and makes sure files reference only existing variables.
+/// Returns `false` if any problem was found
+pub fn run(config: &Config) -> bool {
+    let mut passed = true;
+
+    let themes = config.all_vars();
+
+    println!("{}", "Themes:".purple());
+    for (name, vars) in &themes {
+        let problems = check_theme(config, vars, &config.literals(name));
+        passed &= report(name, &problems);
+    }
+
+    println!("{}", "Files:".purple());
+    for (name, file) in &config.files {
+        let mut problems = vec![];
+
+        for block in file.flatten() {
+            problems.extend(check_block(config, &themes, &block));
+        }
+
+        passed &= report(name, &problems);
+    }
+
+    passed
+}
+
+/// Lists variables of the theme that are missing or have wrong type according to `schema`
+fn check_theme(config: &Config, vars: &ThemeVars, literals: &HashSet<String>) -> Vec<String> {
+    let mut problems = vec![];
+
+    for (key, value_type) in &config.schema {
+        match vars.get(key) {
+            Some(v) if !value_type.matches(v, literals.contains(key)) => problems.push(format!(
+                "`{key}` is expected to be {value_type:?}, got `{v}`"
+            )),
+            Some(_) => (),
+            None => problems.push(format!("`{key}` is missing")),
+        }
+    }
+
+    problems
+}
+
+fn report(name: &str, problems: &[String]) -> bool {
+    if problems.is_empty() {
+        println!("{} {name}", "ok".green());
+        return true;
+    }
+
+    println!("{} {name}", "err".red());
+    for p in problems {
+        println!("  - {p}");
+    }
+
+    false
+}
+
+/// Lists references to variables that do not exist in some of the themes
+fn check_block(
+    config: &Config,
+    themes: &BTreeMap<String, ThemeVars>,
+    block: &BlockConfig,
+) -> Vec<String> {
+    let opts = &block.block;
+    let location = match &block.tag {
+        Some(tag) => format!("block `{tag}`: "),
+        None => String::new(),
+    };
+    let mut problems = vec![];
+
+    // Keys that are used by the file before aliasing
+    let mut original = vec![];
+    // Keys that are available after aliasing
+    let mut aliased = vec![];
+
+    if let Some(aliases) = &opts.aliases {
+        original.extend(aliases.values().map(|k| ("aliases", k.clone())));
+    }
+    aliased.extend(opts.only.iter().map(|k| ("only", k.clone())));
+    aliased.extend(opts.ignore.iter().map(|k| ("ignore", k.clone())));
+    aliased.extend(opts.formats.keys().map(|k| ("formats", k.clone())));
+
+    let formats = std::iter::once(("format", &opts.format))
+        .chain(opts.formats.values().map(|f| ("formats", f)));
+    for (field, format) in formats {
+        for unknown in BlockGenerator::unknown_transforms(format) {
+            problems.push(format!(
+                "{location}unknown key transformation `{unknown}` in `{field}`"
+            ));
+        }
+    }
+
+    // Capture groups of `replace` patterns are not theme variables
+    let mut captures = vec![];
+    for rule in &block.replace {
+        match rule.regex() {
+            Ok(re) => captures.extend(
+                re.capture_names()
+                    .enumerate()
+                    .map(|(i, name)| format!("<{}>", name.map_or(i.to_string(), str::to_owned))),
+            ),
+            Err(e) => problems.push(format!("{location}{e}")),
+        }
+    }
+
+    let template = block
+        .template_path()
+        .and_then(|path| match path {
+            Some(p) => fs::read_to_string(&p)
+                .map(Some)
+                .map_err(|e| format!("Failed to read template {p}: {e}")),
+            None => Ok(None),
+        })
+        .unwrap_or_else(|e| {
+            problems.push(format!("{location}{e}"));
+            None
+        });
+
+    let templates = opts
+        .custom
+        .iter()
+        .map(|t| ("custom", t))
+        .chain(block.set.values().map(|t| ("set", t)))
+        .chain(block.replace.iter().map(|r| ("replace", &r.with)))
+        .chain(template.iter().map(|t| ("template", t)));
+    for (field, template) in templates {
+        aliased.extend(
+            BlockGenerator::extract_vars(template)
+                .into_iter()
+                .filter(|t| t != "<vars>" && t != "<name>" && !t.starts_with("<import "))
+                .filter(|t| !captures.contains(t))
+                .map(|t| {
+                    (
+                        field,
+                        t.trim_start_matches('<').trim_end_matches('>').to_owned(),
+                    )
+                }),
+        );
+    }
+
+    let alias_names: Vec<&String> = opts.aliases.iter().flat_map(|a| a.keys()).collect();
+
+    for (field, key) in original.into_iter().chain(
+        aliased
+            .into_iter()
+            .filter(|(_, k)| !alias_names.contains(&k)),
+    ) {
+        let group = format!("{key}.");
+        let declared = config
+            .schema
+            .keys()
+            .any(|k| *k == key || k.starts_with(&group));
+
+        if !config.schema.is_empty() && !declared {
+            problems.push(format!(
+                "{location}`{key}` in `{field}` is not declared in schema"
+            ));
+            continue;
+        }
+
+        let missing: Vec<&str> = themes
+            .iter()
+            .filter(|(_, vars)| !has_key(vars, &key))
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        if !missing.is_empty() {
+            problems.push(format!(
+                "{location}`{key}` in `{field}` is missing in themes: {}",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    problems.sort();
+    problems.dedup();
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_block, check_theme};
+    use crate::config::Config;
+
+    fn load(contents: &str) -> Config {
+        serde_yaml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn types() {
+        let config = load(
+            "schema:
+  bg: color
+  size: number
+  bold: bool
+  font: string
+  gap: any
+  missing: any
+themes:
+  good: { bg: \"#000\", size: 12, bold: true, font: Mono, gap: 1 }
+  bad: { bg: black, size: \"12\", bold: \"yes\", font: 12, gap: x }
+",
+        );
+        let problems = |theme: &str| {
+            check_theme(
+                &config,
+                &config.vars(theme).unwrap(),
+                &config.literals(theme),
+            )
+        };
+
+        assert_eq!(problems("good"), vec!["`missing` is missing"]);
+        assert_eq!(
+            problems("bad"),
+            vec![
+                "`bg` is expected to be Color, got `black`",
+                "`bold` is expected to be Bool, got `yes`",
+                "`font` is expected to be String, got `12`",
+                "`missing` is missing",
+                "`size` is expected to be Number, got `12`",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_variables() {
+        let config = load(
+            "themes:
+  dark: { bg: \"#000\", ui: { gap: 1 } }
+  light: { bg: \"#fff\" }
+files:
+  bar:
+    path: /
+    only: [ui, fg]
+    format: \"<key|shout> <value>\"
+    custom: \"<vars> <name> <bg> <ui.gap>\"
+    aliases: { background: bg }
+",
+        );
+        let block = &config.files.get("bar").unwrap().flatten()[0];
+
+        assert_eq!(
+            check_block(&config, &config.all_vars(), block),
+            vec![
+                "`fg` in `only` is missing in themes: dark, light",
+                "`ui.gap` in `custom` is missing in themes: light",
+                "`ui` in `only` is missing in themes: light",
+                "unknown key transformation `shout` in `format`",
+            ]
+        );
+
+        let schema = load(
+            "schema: { bg: color }
+themes:
+  dark: { bg: \"#000\" }
+files:
+  bar: { path: /, custom: \"<bg> <fg>\" }
+",
+        );
+        let block = &schema.files.get("bar").unwrap().flatten()[0];
+        assert_eq!(
+            check_block(&schema, &schema.all_vars(), block),
+            vec!["`fg` in `custom` is not declared in schema"]
+        );
+    }
+}
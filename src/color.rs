@@ -0,0 +1,145 @@
+/// RGB color parsed from a theme variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Parses hex colors in `#rgb`, `#rrggbb` and `#rrggbbaa` notations (alpha is dropped)
+    pub fn parse(value: &str) -> Option<Self> {
+        let hex = value.trim().strip_prefix('#')?;
+
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+
+        match hex.len() {
+            3 => {
+                // Each digit is doubled: `#fa0` is the same as `#ffaa00`
+                let digits: Vec<u8> = hex
+                    .chars()
+                    .map(|c| channel(&c.to_string().repeat(2)))
+                    .collect::<Option<_>>()?;
+
+                Some(Self {
+                    r: digits[0],
+                    g: digits[1],
+                    b: digits[2],
+                })
+            }
+            6 | 8 => Some(Self {
+                r: channel(&hex[0..2])?,
+                g: channel(&hex[2..4])?,
+                b: channel(&hex[4..6])?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Relative luminance as defined by WCAG 2.x
+    pub fn luminance(self) -> f64 {
+        let linear = |v: u8| {
+            let v = v as f64 / 255.0;
+            if v <= 0.03928 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Contrast ratio between two colors, ranges from 1 to 21
+    pub fn contrast(self, other: Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        let (light, dark) = if a > b { (a, b) } else { (b, a) };
+
+        (light + 0.05) / (dark + 0.05)
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// Finds the closest color in xterm's 256 color palette
+    pub fn to_ansi256(self) -> u8 {
+        // Grayscale ramp is more precise for colors without a hue
+        if self.r == self.g && self.g == self.b {
+            return match self.r {
+                0..=7 => 16,
+                249..=255 => 231,
+                v => 232 + ((v - 8) / 10).min(23),
+            };
+        }
+
+        let cube = |v: u8| -> u8 {
+            match v {
+                0..=47 => 0,
+                48..=114 => 1,
+                v => ((v - 35) / 40).min(5),
+            }
+        };
+
+        16 + 36 * cube(self.r) + 6 * cube(self.g) + cube(self.b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Color;
+
+    #[test]
+    fn parse_hex() {
+        let full = Color::parse("#ff8000").unwrap();
+        assert_eq!(
+            full,
+            Color {
+                r: 255,
+                g: 128,
+                b: 0
+            }
+        );
+        assert_eq!(
+            Color::parse("#f80"),
+            Some(Color {
+                r: 255,
+                g: 136,
+                b: 0
+            })
+        );
+        assert_eq!(Color::parse("#ff8000cc"), Some(full));
+        assert_eq!(full.to_hex(), "#ff8000");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(Color::parse("ff8000"), None);
+        assert_eq!(Color::parse("#ff80"), None);
+        assert_eq!(Color::parse("#gggggg"), None);
+        assert_eq!(Color::parse("monospace 10"), None);
+    }
+
+    #[test]
+    fn contrast() {
+        let black = Color::parse("#000").unwrap();
+        let white = Color::parse("#fff").unwrap();
+
+        assert!((black.contrast(white) - 21.0).abs() < 0.01);
+        assert!((white.contrast(white) - 1.0).abs() < 0.01);
+        let gray = Color::parse("#777777").unwrap();
+        assert!((gray.contrast(white) - 4.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn ansi256() {
+        assert_eq!(Color::parse("#000000").unwrap().to_ansi256(), 16);
+        assert_eq!(Color::parse("#ffffff").unwrap().to_ansi256(), 231);
+        assert_eq!(Color::parse("#ff0000").unwrap().to_ansi256(), 196);
+        assert_eq!(Color::parse("#808080").unwrap().to_ansi256(), 244);
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+/// Opening and optional closing sequences of a single line comment
+pub type CommentSyntax = (&'static str, Option<&'static str>);
+
+/// Well-known file names that can't be recognized by extension
+const FILE_NAMES: &[(&str, CommentSyntax)] = &[
+    ("vimrc", ("\"", None)),
+    (".vimrc", ("\"", None)),
+    (".gvimrc", ("\"", None)),
+    (".Xresources", ("!", None)),
+    (".Xdefaults", ("!", None)),
+    (".emacs", (";;", None)),
+    ("dunstrc", ("#", None)),
+    ("config", ("#", None)),
+];
+
+const EXTENSIONS: &[(&str, CommentSyntax)] = &[
+    ("css", ("/*", Some("*/"))),
+    ("scss", ("//", None)),
+    ("less", ("//", None)),
+    ("rasi", ("/*", Some("*/"))),
+    ("lua", ("--", None)),
+    ("hs", ("--", None)),
+    ("sql", ("--", None)),
+    ("vim", ("\"", None)),
+    ("ini", (";", None)),
+    ("el", (";;", None)),
+    ("js", ("//", None)),
+    ("ts", ("//", None)),
+    ("jsonc", ("//", None)),
+    ("kdl", ("//", None)),
+    ("rs", ("//", None)),
+    ("c", ("//", None)),
+    ("h", ("//", None)),
+    ("cpp", ("//", None)),
+    ("go", ("//", None)),
+    ("html", ("<!--", Some("-->"))),
+    ("xml", ("<!--", Some("-->"))),
+    ("svg", ("<!--", Some("-->"))),
+    ("tex", ("%", None)),
+    ("toml", ("#", None)),
+    ("yml", ("#", None)),
+    ("yaml", ("#", None)),
+    ("conf", ("#", None)),
+    ("sh", ("#", None)),
+    ("bash", ("#", None)),
+    ("zsh", ("#", None)),
+    ("fish", ("#", None)),
+    ("py", ("#", None)),
+    ("nix", ("#", None)),
+];
+
+/// Guesses comment syntax by file name or extension
+pub fn detect(path: &str) -> Option<CommentSyntax> {
+    let path = Path::new(path);
+    let name = path.file_name()?.to_str()?;
+
+    if let Some((_, syntax)) = FILE_NAMES.iter().find(|(n, _)| *n == name) {
+        return Some(*syntax);
+    }
+
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, syntax)| *syntax)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect;
+
+    #[test]
+    fn detect_syntax() {
+        assert_eq!(
+            detect("~/.config/waybar/style.css"),
+            Some(("/*", Some("*/")))
+        );
+        assert_eq!(detect("~/.config/nvim/lua/colors.lua"), Some(("--", None)));
+        assert_eq!(detect("/home/me/.vimrc"), Some(("\"", None)));
+        assert_eq!(
+            detect("/home/me/.config/polybar/config.INI"),
+            Some((";", None))
+        );
+        assert_eq!(detect("/home/me/unknown.xyz"), None);
+    }
+}
@@ -1,14 +1,222 @@
+use crate::{
+    color::Color,
+    comments,
+    structured::Syntax,
+    utils::{expand_path, hostname},
+};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fmt,
+    path::Path,
+    process::{Command, Stdio},
+};
 
-pub type ThemeVars = BTreeMap<String, String>;
+/// Theme variables flattened to strings, nested keys are joined with a dot (`ui.border`).
+/// Variables are kept in the same order they're declared in configuration file
+pub type ThemeVars = IndexMap<String, String>;
+/// Theme as it's declared in configuration file
+pub type Theme = IndexMap<String, ThemeValue>;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ThemeValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Group(Theme),
+}
+
+impl ThemeValue {
+    /// Collects scalar values into `vars`, prefixing keys of nested groups with their parent key
+    fn flatten_into(&self, key: String, vars: &mut ThemeVars) {
+        let value = match self {
+            ThemeValue::Bool(v) => v.to_string(),
+            ThemeValue::Int(v) => v.to_string(),
+            // Debug keeps the decimal point of whole numbers, so `1.0` stays a float
+            ThemeValue::Float(v) => format!("{v:?}"),
+            ThemeValue::String(v) => v.clone(),
+            ThemeValue::Group(group) => {
+                for (k, v) in group {
+                    v.flatten_into(format!("{key}.{k}"), vars);
+                }
+                return;
+            }
+        };
+
+        vars.insert(key, value);
+    }
+}
+
+/// Keys of flattened variables that are booleans or finite numbers
+pub fn literal_vars(theme: &Theme) -> HashSet<String> {
+    fn collect(theme: &Theme, prefix: &str, keys: &mut HashSet<String>) {
+        for (k, v) in theme {
+            let key = format!("{prefix}{k}");
+            match v {
+                ThemeValue::Bool(_) | ThemeValue::Int(_) => {
+                    keys.insert(key);
+                }
+                ThemeValue::Float(f) if f.is_finite() => {
+                    keys.insert(key);
+                }
+                ThemeValue::Group(group) => collect(group, &format!("{key}."), keys),
+                _ => (),
+            }
+        }
+    }
+
+    let mut keys = HashSet::new();
+    collect(theme, "", &mut keys);
+
+    keys
+}
+
+pub fn flatten_theme(theme: &Theme) -> ThemeVars {
+    let mut vars = ThemeVars::new();
+    for (k, v) in theme {
+        v.flatten_into(k.clone(), &mut vars);
+    }
+
+    vars
+}
+
+/// Keys accepted by `Config`, used to report unknown keys
+pub const CONFIG_KEYS: &[&str] = &[
+    "include", "themes", "files", "disable", "reload", "lint", "schema", "profiles", "hosts",
+];
+/// Keys accepted by `Overrides`
+pub const OVERRIDES_KEYS: &[&str] = &["enable", "disable", "files", "vars", "themes"];
+/// Keys accepted by `BlockOptions`
+pub const BLOCK_OPTIONS_KEYS: &[&str] = &[
+    "only",
+    "ignore",
+    "aliases",
+    "format",
+    "separator",
+    "sort",
+    "custom",
+    "all_occurrences",
+    "indent",
+    "formats",
+    "key_prefix",
+    "escape",
+];
+/// Keys shared by `BlockConfig` and `TaggedConfig`
+pub const FILE_KEYS: &[&str] = &[
+    "path",
+    "comment",
+    "closing_comment",
+    "start_marker",
+    "end_marker",
+    "when",
+    "groups",
+];
+/// Keys accepted only by `BlockConfig`
+pub const SINGLE_FILE_KEYS: &[&str] = &["set", "syntax", "replace", "template", "header"];
+pub const LINT_KEYS: &[&str] = &["level", "pairs"];
+pub const WHEN_KEYS: &[&str] = &["exists", "env", "command", "hostname"];
+/// Names of `When` keywords, `SortOrder`, `Escape`, `Syntax`, `ContrastLevel` and `ValueType`
+/// variants, used to report unknown values
+pub const WHEN_KEYWORDS: &[&str] = &["exists"];
+pub const SORT_ORDERS: &[&str] = &["declared", "alpha", "natural"];
+pub const ESCAPES: &[&str] = &["none", "shell", "json", "toml", "lua", "css"];
+pub const SYNTAXES: &[&str] = &["json", "toml", "yaml", "ini"];
+pub const CONTRAST_LEVELS: &[&str] = &["AA", "AAA"];
+pub const VALUE_TYPES: &[&str] = &["any", "color", "number", "bool", "string"];
+
+/// Path of the file entry: a single path or a list of them, each may be a glob pattern
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Paths {
+    Single(String),
+    List(Vec<String>),
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Paths::Single(String::new())
+    }
+}
+
+impl fmt::Display for Paths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Paths::Single(p) => write!(f, "{p}"),
+            Paths::List(list) => write!(f, "{}", list.join(", ")),
+        }
+    }
+}
+
+impl Paths {
+    /// Expands variables and glob patterns into the list of existing files.
+    /// Paths without glob characters are kept as is even if the file does not exist
+    pub fn resolve(&self) -> Result<Vec<String>, String> {
+        let patterns = match self {
+            Paths::Single(p) => vec![p.clone()],
+            Paths::List(list) => list.clone(),
+        };
+
+        let mut resolved = vec![];
+        for pattern in patterns {
+            let pattern = expand_path(&pattern)?;
+
+            if !pattern.contains(['*', '?', '[']) {
+                resolved.push(pattern);
+                continue;
+            }
+
+            let matches = glob::glob(&pattern)
+                .map_err(|e| format!("Invalid glob pattern `{pattern}`: {e}"))?
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file())
+                .map(|p| p.display().to_string());
+
+            let before = resolved.len();
+            resolved.extend(matches);
+            if resolved.len() == before {
+                log::warn!("No files match `{pattern}`");
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BlockConfig {
-    pub path: String,
-    #[serde(default = "default_comment")]
-    pub comment: String,
+    pub path: Paths,
+    /// Detected by file extension when not set, see `comments::detect`
+    pub comment: Option<String>,
     pub closing_comment: Option<String>,
+    /// Name of the block start marker, `{tag}` is replaced with the block tag
+    #[serde(default = "default_start_marker")]
+    pub start_marker: String,
+    /// Name of the block end marker, `{tag}` is replaced with the block tag
+    #[serde(default = "default_end_marker")]
+    pub end_marker: String,
+    pub when: Option<When>,
+    /// Named groups used to select files with `--only` and `--exclude`
+    #[serde(default)]
+    pub groups: Vec<String>,
+
+    /// Values changed in place instead of updating Themer block, `key.path: template`
+    #[serde(default)]
+    pub set: IndexMap<String, String>,
+    /// Syntax of the file for `set`, detected by extension when not set
+    pub syntax: Option<Syntax>,
+    /// Patterns rewritten instead of updating Themer block
+    #[serde(default)]
+    pub replace: Vec<ReplaceRule>,
+    /// Path to the template the whole file is rendered from
+    pub template: Option<String>,
+    /// Put a comment warning that the file is generated at the top of rendered template
+    #[serde(default)]
+    pub header: bool,
 
     #[serde(skip)]
     pub tag: Option<String>,
@@ -17,15 +225,221 @@ pub struct BlockConfig {
     pub block: BlockOptions,
 }
 
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            path: Paths::default(),
+            comment: None,
+            closing_comment: None,
+            start_marker: default_start_marker(),
+            end_marker: default_end_marker(),
+            when: None,
+            groups: vec![],
+            set: IndexMap::new(),
+            syntax: None,
+            replace: vec![],
+            template: None,
+            header: false,
+            tag: None,
+            block: BlockOptions::default(),
+        }
+    }
+}
+
+impl BlockConfig {
+    pub fn comment(&self) -> &str {
+        self.comment.as_deref().unwrap_or(DEFAULT_COMMENT)
+    }
+
+    /// Whether the file is updated with Themer blocks, not with `set`, `replace` or `template`
+    pub fn has_markers(&self) -> bool {
+        self.set.is_empty() && self.replace.is_empty() && self.template.is_none()
+    }
+
+    /// Path of `template`, resolved the same way as `path`. Glob pattern must match a single file
+    pub fn template_path(&self) -> Result<Option<String>, String> {
+        let template = match &self.template {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        let mut paths = Paths::Single(template.clone()).resolve()?;
+        match paths.len() {
+            1 => Ok(paths.pop()),
+            n => Err(format!(
+                "Template `{template}` matches {n} files instead of one"
+            )),
+        }
+    }
+
+    pub fn syntax(&self) -> Option<Syntax> {
+        self.syntax
+            .or_else(|| Syntax::detect(&self.path.to_string()))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TaggedConfig {
-    pub path: String,
-    #[serde(default = "default_comment")]
-    pub comment: String,
+    pub path: Paths,
+    pub comment: Option<String>,
     pub closing_comment: Option<String>,
+    #[serde(default = "default_start_marker")]
+    pub start_marker: String,
+    #[serde(default = "default_end_marker")]
+    pub end_marker: String,
+    pub when: Option<When>,
+    #[serde(default)]
+    pub groups: Vec<String>,
     pub blocks: BTreeMap<String, BlockOptions>,
 }
 
+/// Condition of processing the file entry
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum When {
+    /// Only `exists` keyword is supported
+    Keyword(WhenKeyword),
+    Conditions(Conditions),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WhenKeyword {
+    Exists,
+}
+
+/// Every condition that is set must be met
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct Conditions {
+    /// File should exist
+    #[serde(default)]
+    pub exists: bool,
+    /// Environment variable should be set and not empty
+    pub env: Option<String>,
+    /// Shell command should succeed
+    pub command: Option<String>,
+    /// Hostname should match
+    pub hostname: Option<String>,
+}
+
+impl When {
+    fn conditions(&self) -> Conditions {
+        match self {
+            When::Keyword(WhenKeyword::Exists) => Conditions {
+                exists: true,
+                ..Default::default()
+            },
+            When::Conditions(c) => c.clone(),
+        }
+    }
+
+    /// Describes the first condition of the entry that is not met. They don't depend on the
+    /// path, so they are checked once for all files matched by the entry
+    pub fn unmet(&self) -> Option<String> {
+        let conditions = self.conditions();
+
+        if let Some(var) = &conditions.env {
+            if env::var(var).map(|v| v.is_empty()).unwrap_or(true) {
+                return Some(format!("`{var}` is not set"));
+            }
+        }
+
+        if let Some(cmd) = &conditions.command {
+            let success = Command::new("sh")
+                .args(["-c", cmd])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false);
+
+            if !success {
+                return Some(format!("`{cmd}` failed"));
+            }
+        }
+
+        if let Some(host) = &conditions.hostname {
+            if hostname().as_ref() != Some(host) {
+                return Some(format!("hostname is not `{host}`"));
+            }
+        }
+
+        None
+    }
+
+    /// Checks `exists` condition for one of the files matched by the entry
+    pub fn missing(&self, path: &str) -> Option<String> {
+        match self.conditions().exists && !Path::new(path).exists() {
+            true => Some("file does not exist".to_owned()),
+            false => None,
+        }
+    }
+}
+
+/// Every match of `pattern` is replaced with `with` template, where capture groups are available
+/// as variables: `<1>` or `<name>` for named ones
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplaceRule {
+    pub pattern: String,
+    pub with: String,
+}
+
+impl ReplaceRule {
+    /// Compiled pattern, `^` and `$` match at the beginning and the end of lines
+    pub fn regex(&self) -> Result<Regex, String> {
+        RegexBuilder::new(&self.pattern)
+            .multi_line(true)
+            .build()
+            .map_err(|e| format!("Invalid pattern `{}`: {e}", self.pattern))
+    }
+
+    /// Splits `with` into text and references to capture groups (`<1>`, `<name>`)
+    pub fn pieces(&self, re: &Regex) -> Vec<Piece> {
+        lazy_static! {
+            static ref REFERENCE_RE: Regex = Regex::new(r"<(\w+)>").unwrap();
+        }
+
+        let names: Vec<Option<&str>> = re.capture_names().collect();
+        let group = |reference: &str| match reference.parse::<usize>() {
+            Ok(i) => (i < names.len()).then_some(i),
+            Err(_) => names.iter().position(|n| *n == Some(reference)),
+        };
+
+        let mut pieces = vec![];
+        let mut last = 0;
+        for caps in REFERENCE_RE.captures_iter(&self.with) {
+            let reference = caps.get(0).unwrap();
+            if let Some(i) = group(&caps[1]) {
+                pieces.push(Piece::Text(self.with[last..reference.start()].to_owned()));
+                pieces.push(Piece::Group(i));
+                last = reference.end();
+            }
+        }
+        pieces.push(Piece::Text(self.with[last..].to_owned()));
+
+        pieces
+    }
+
+    /// Replacement for the match, captured text is put in as is
+    pub fn fill(pieces: &[Piece], caps: &Captures) -> String {
+        pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.as_str(),
+                Piece::Group(i) => caps.get(*i).map_or("", |m| m.as_str()),
+            })
+            .collect()
+    }
+}
+
+/// Part of `replace` template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    Text(String),
+    /// Index of capture group
+    Group(usize),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct BlockOptions {
     #[serde(default)]
@@ -37,16 +451,76 @@ pub struct BlockOptions {
 
     #[serde(default = "default_format")]
     pub format: String,
+    /// Formats of specific keys (or groups) used instead of `format`
+    #[serde(default)]
+    pub formats: IndexMap<String, String>,
+    /// Put before every key in default block, after transformations
+    #[serde(default)]
+    pub key_prefix: String,
+    /// Quoting of values in default block
+    #[serde(default)]
+    pub escape: Escape,
+    /// Replaces dots in keys of nested variables when generating default block
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    /// Order of variables in default block
+    #[serde(default)]
+    pub sort: SortOrder,
     pub custom: Option<String>,
+    /// Update every block with the tag instead of the first one
+    #[serde(default)]
+    pub all_occurrences: bool,
+    /// Indentation of generated lines, same as the start marker's one by default
+    pub indent: Option<Indent>,
+}
+
+/// Indentation set either as number of spaces or as string (`"\t"`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Indent {
+    Width(usize),
+    Text(String),
+}
+
+impl Indent {
+    pub fn text(&self) -> String {
+        match self {
+            Indent::Width(w) => " ".repeat(*w),
+            Indent::Text(t) => t.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Same order as in the theme
+    #[default]
+    Declared,
+    /// Alphabetical order
+    Alpha,
+    /// Alphabetical order that compares numbers by value, so `color2` goes before `color10`
+    Natural,
 }
 
-fn default_comment() -> String {
-    "#".to_owned()
+/// Comment used when it's not set and can't be detected
+pub const DEFAULT_COMMENT: &str = "#";
+
+fn default_start_marker() -> String {
+    "THEMER".to_owned()
+}
+fn default_end_marker() -> String {
+    "THEMER_END".to_owned()
 }
 fn default_format() -> String {
     "<key> = <value>".to_owned()
 }
+fn default_separator() -> String {
+    "_".to_owned()
+}
 
+// Entries are few and boxing would complicate matching on them
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum FileConfig {
@@ -57,10 +531,61 @@ pub enum FileConfig {
 impl FileConfig {
     pub fn get_path(&self) -> String {
         match self {
-            FileConfig::Single(v) => v.path.clone(),
-            FileConfig::Multi(v) => v.path.clone(),
+            FileConfig::Single(v) => v.path.to_string(),
+            FileConfig::Multi(v) => v.path.to_string(),
         }
     }
+
+    pub fn when(&self) -> Option<&When> {
+        match self {
+            FileConfig::Single(v) => v.when.as_ref(),
+            FileConfig::Multi(v) => v.when.as_ref(),
+        }
+    }
+
+    pub fn groups(&self) -> &[String] {
+        match self {
+            FileConfig::Single(v) => &v.groups,
+            FileConfig::Multi(v) => &v.groups,
+        }
+    }
+
+    /// Splits the entry into entries with a single resolved path each
+    pub fn expand(&self) -> Result<Vec<FileConfig>, String> {
+        let paths = match self {
+            FileConfig::Single(v) => &v.path,
+            FileConfig::Multi(v) => &v.path,
+        };
+
+        Ok(paths
+            .resolve()?
+            .into_iter()
+            .map(|path| {
+                let mut file = self.clone();
+                let (comment, closing) = match &mut file {
+                    FileConfig::Single(v) => (&mut v.comment, &mut v.closing_comment),
+                    FileConfig::Multi(v) => (&mut v.comment, &mut v.closing_comment),
+                };
+
+                // Explicit `comment` always wins, detected closing comment is used only with
+                // detected opening one
+                if comment.is_none() {
+                    if let Some((open, close)) = comments::detect(&path) {
+                        *comment = Some(open.to_owned());
+                        if closing.is_none() {
+                            *closing = close.map(str::to_owned);
+                        }
+                    }
+                }
+
+                match &mut file {
+                    FileConfig::Single(v) => v.path = Paths::Single(path),
+                    FileConfig::Multi(v) => v.path = Paths::Single(path),
+                }
+                file
+            })
+            .collect())
+    }
     pub fn flatten(&self) -> Vec<BlockConfig> {
         match self {
             FileConfig::Single(v) => vec![v.clone()],
@@ -73,6 +598,15 @@ impl FileConfig {
                     path: mutli.path.clone(),
                     comment: mutli.comment.clone(),
                     closing_comment: mutli.closing_comment.clone(),
+                    start_marker: mutli.start_marker.clone(),
+                    end_marker: mutli.end_marker.clone(),
+                    when: mutli.when.clone(),
+                    groups: mutli.groups.clone(),
+                    set: IndexMap::new(),
+                    syntax: None,
+                    replace: vec![],
+                    template: None,
+                    header: false,
                     block,
                 })
                 .collect(),
@@ -80,9 +614,428 @@ impl FileConfig {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Quoting and escaping of values for the syntax of the file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Escape {
+    /// Values are inserted as is
+    #[default]
+    None,
+    /// Single-quoted string
+    Shell,
+    Json,
+    Toml,
+    Lua,
+    /// Values that are valid CSS, like colors, shorthands, font stacks and functions, are left
+    /// as is. Other values are quoted
+    Css,
+}
+
+impl Escape {
+    /// `literal` values are numbers and booleans of the theme, they keep their type in formats
+    /// that have one
+    pub fn apply(&self, value: &str, literal: bool) -> String {
+        match self {
+            Escape::None => value.to_owned(),
+            Escape::Shell => format!("'{}'", value.replace('\'', r"'\''")),
+            Escape::Json | Escape::Toml | Escape::Lua if literal => value.to_owned(),
+            // JSON escapes are valid in TOML basic strings as well
+            Escape::Json | Escape::Toml => serde_json::Value::from(value).to_string(),
+            Escape::Lua => {
+                let mut quoted = String::from('"');
+                for c in value.chars() {
+                    match c {
+                        '"' => quoted.push_str("\\\""),
+                        '\\' => quoted.push_str("\\\\"),
+                        '\n' => quoted.push_str("\\n"),
+                        '\r' => quoted.push_str("\\r"),
+                        '\t' => quoted.push_str("\\t"),
+                        c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03}", c as u32)),
+                        c => quoted.push(c),
+                    }
+                }
+                quoted.push('"');
+                quoted
+            }
+            Escape::Css if css_value(value) => value.to_owned(),
+            Escape::Css => {
+                let escaped = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\A ");
+                format!("\"{escaped}\"")
+            }
+        }
+    }
+}
+
+/// Whether the value can be used as CSS value without quoting: it's not empty, doesn't end the
+/// declaration or block, and its parentheses and quotes are balanced
+fn css_value(value: &str) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    for c in value.chars() {
+        match (quote, c) {
+            (_, '"' | ';' | '{' | '}' | '\\' | '\n' | '\r') => return false,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return false,
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+
+    !value.trim().is_empty() && depth == 0 && quote.is_none()
+}
+
+/// Type of theme variable value declared in `schema`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    #[default]
+    Any,
+    Color,
+    Number,
+    Bool,
+    String,
+}
+
+impl ValueType {
+    /// `literal` values are numbers and booleans of the theme, quoted ones are strings
+    pub fn matches(&self, value: &str, literal: bool) -> bool {
+        match self {
+            ValueType::Any => true,
+            ValueType::String => !literal,
+            ValueType::Color => Color::parse(value).is_some(),
+            ValueType::Number => literal && value.parse::<f64>().is_ok(),
+            ValueType::Bool => literal && (value == "true" || value == "false"),
+        }
+    }
+}
+
+/// WCAG conformance level used by `themer lint`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContrastLevel {
+    #[default]
+    #[serde(rename = "AA")]
+    Aa,
+    #[serde(rename = "AAA")]
+    Aaa,
+}
+
+impl ContrastLevel {
+    /// Minimal contrast ratio for normal text
+    pub fn threshold(&self) -> f64 {
+        match self {
+            ContrastLevel::Aa => 4.5,
+            ContrastLevel::Aaa => 7.0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContrastLevel::Aa => "AA",
+            ContrastLevel::Aaa => "AAA",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LintConfig {
+    #[serde(default)]
+    pub level: ContrastLevel,
+    /// Pairs of `[foreground, background]` variables to check in addition to the default one
+    #[serde(default)]
+    pub pairs: Vec<[String; 2]>,
+}
+
+impl LintConfig {
+    pub fn all_pairs(&self) -> Vec<[String; 2]> {
+        let mut pairs = vec![["foreground".to_owned(), "background".to_owned()]];
+        for pair in &self.pairs {
+            if !pairs.contains(pair) {
+                pairs.push(pair.clone());
+            }
+        }
+
+        pairs
+    }
+}
+
+/// Changes applied to configuration on specific hosts or with `--profile` flag
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Overrides {
+    /// Files disabled by the base config or previously applied overrides that should be processed again
+    #[serde(default)]
+    pub enable: Vec<String>,
+    /// Files that should not be processed
+    #[serde(default)]
+    pub disable: Vec<String>,
+    /// File entries that are added or replace existing ones
+    #[serde(default)]
+    pub files: BTreeMap<String, FileConfig>,
+    /// Variables that are set in every theme
+    #[serde(default)]
+    pub vars: Theme,
+    /// Variables that are set in specific themes
+    #[serde(default)]
+    pub themes: BTreeMap<String, Theme>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
-    pub themes: BTreeMap<String, ThemeVars>,
+    /// Other configuration files to merge into this one
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub themes: BTreeMap<String, Theme>,
+    #[serde(default)]
     pub files: BTreeMap<String, FileConfig>,
+    /// Files that are not processed unless enabled by host or profile overrides
+    #[serde(default)]
+    pub disable: Vec<String>,
     pub reload: Option<String>,
+    pub lint: Option<LintConfig>,
+    /// Keys required to be present in every theme
+    #[serde(default)]
+    pub schema: BTreeMap<String, ValueType>,
+    /// Overrides selected with `--profile` flag
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Overrides>,
+    /// Overrides applied when hostname matches the key
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Overrides>,
+}
+
+impl Config {
+    /// Flattened variables of the theme
+    pub fn vars(&self, theme: &str) -> Option<ThemeVars> {
+        self.themes.get(theme).map(flatten_theme)
+    }
+
+    /// Variables of the theme that are booleans or numbers
+    pub fn literals(&self, theme: &str) -> HashSet<String> {
+        self.themes.get(theme).map(literal_vars).unwrap_or_default()
+    }
+
+    /// Keeps only files that belong to any of `only` groups and none of `exclude` groups.
+    /// Name of the file entry is matched as a group too. Empty `only` keeps every file
+    pub fn select_files(&mut self, only: &[String], exclude: &[String]) {
+        let in_any = |name: &String, file: &FileConfig, groups: &[String]| {
+            groups
+                .iter()
+                .any(|g| g == name || file.groups().contains(g))
+        };
+
+        for group in only.iter().chain(exclude) {
+            if !self
+                .files
+                .iter()
+                .any(|(n, f)| in_any(n, f, std::slice::from_ref(group)))
+            {
+                log::warn!("No files belong to group `{group}`");
+            }
+        }
+
+        self.files.retain(|name, file| {
+            (only.is_empty() || in_any(name, file, only)) && !in_any(name, file, exclude)
+        });
+    }
+
+    /// Flattened variables of every theme
+    pub fn all_vars(&self) -> BTreeMap<String, ThemeVars> {
+        self.themes
+            .iter()
+            .map(|(name, theme)| (name.clone(), flatten_theme(theme)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Conditions, Config, ContrastLevel, Escape, Paths, Piece, ReplaceRule, SortOrder, ValueType,
+        When, WhenKeyword, CONTRAST_LEVELS, ESCAPES, SORT_ORDERS, SYNTAXES, VALUE_TYPES,
+        WHEN_KEYWORDS,
+    };
+    use crate::structured::Syntax;
+    use serde::de::DeserializeOwned;
+
+    #[test]
+    fn conditions() {
+        let exists = When::Keyword(WhenKeyword::Exists);
+        assert_eq!(exists.unmet(), None);
+        assert_eq!(exists.missing("./test-configs/config.yml"), None);
+        assert_eq!(
+            exists.missing("./test-configs/missing.yml").as_deref(),
+            Some("file does not exist")
+        );
+
+        let env = When::Conditions(Conditions {
+            env: Some("THEMER_SURELY_NOT_SET".to_owned()),
+            ..Default::default()
+        });
+        assert!(env.unmet().is_some());
+        assert_eq!(env.missing("./test-configs/missing.yml"), None);
+
+        let command = |cmd: &str| {
+            When::Conditions(Conditions {
+                command: Some(cmd.to_owned()),
+                ..Default::default()
+            })
+        };
+        assert_eq!(command("true").unmet(), None);
+        assert_eq!(command("false").unmet().as_deref(), Some("`false` failed"));
+    }
+
+    #[test]
+    fn select_files() {
+        let mut conf: Config = serde_yaml::from_str(
+            "files:
+  kitty: { path: /, groups: [terminal] }
+  nvim: { path: /, groups: [editor, terminal] }
+  polybar: { path: /, groups: [bar] }
+",
+        )
+        .unwrap();
+
+        conf.select_files(&["terminal".to_owned()], &["editor".to_owned()]);
+        assert_eq!(conf.files.keys().collect::<Vec<_>>(), vec!["kitty"]);
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let glob = Paths::Single("./test-configs/split/*.d/*.yml".to_owned());
+        assert_eq!(
+            glob.resolve().unwrap(),
+            vec![
+                "test-configs/split/files.d/bar.yml",
+                "test-configs/split/themes.d/light.yml"
+            ]
+        );
+
+        let list = Paths::List(vec![
+            "/not/a/glob".to_owned(),
+            "./test-configs/imports/*".to_owned(),
+        ]);
+        assert_eq!(
+            list.resolve().unwrap(),
+            vec!["/not/a/glob", "test-configs/imports/test-theme"]
+        );
+    }
+
+    #[test]
+    fn replace_rules() {
+        let rule = ReplaceRule {
+            pattern: r"^colorscheme (?P<old>\w+)$".to_owned(),
+            with: "colorscheme <scheme> \" was <old>, <1>".to_owned(),
+        };
+        let re = rule.regex().unwrap();
+        let contents = "set nu\ncolorscheme gruvbox\n";
+
+        let pieces = rule.pieces(&re);
+        assert_eq!(
+            pieces,
+            vec![
+                Piece::Text("colorscheme <scheme> \" was ".to_owned()),
+                Piece::Group(1),
+                Piece::Text(", ".to_owned()),
+                Piece::Group(1),
+                Piece::Text(String::new()),
+            ]
+        );
+        assert_eq!(
+            re.replace_all(contents, |caps: &regex::Captures| ReplaceRule::fill(
+                &pieces, caps
+            )),
+            "set nu\ncolorscheme <scheme> \" was gruvbox, gruvbox\n"
+        );
+
+        let invalid = ReplaceRule {
+            pattern: "(".to_owned(),
+            with: String::new(),
+        };
+        assert!(invalid
+            .regex()
+            .unwrap_err()
+            .starts_with("Invalid pattern `(`"));
+    }
+
+    #[test]
+    fn escapes() {
+        let value = "it's \"$HOME\"\\";
+
+        assert_eq!(Escape::None.apply(value, false), value);
+        assert_eq!(Escape::Shell.apply(value, false), r#"'it'\''s "$HOME"\'"#);
+        assert_eq!(Escape::Json.apply(value, false), r#""it's \"$HOME\"\\""#);
+        assert_eq!(Escape::Toml.apply("12", true), "12");
+        // Strings stay strings, even if they look like numbers
+        assert_eq!(Escape::Toml.apply("12", false), r#""12""#);
+        assert_eq!(Escape::Json.apply(".5", false), r#"".5""#);
+        assert_eq!(Escape::Lua.apply("a\nb\u{1}", false), r#""a\nb\001""#);
+        assert_eq!(Escape::Lua.apply("true", true), "true");
+        assert_eq!(Escape::Css.apply("#fff", false), "#fff");
+        assert_eq!(
+            Escape::Css.apply("rgba(0, 0, 0, 0.5)", false),
+            "rgba(0, 0, 0, 0.5)"
+        );
+        assert_eq!(Escape::Css.apply("1px solid #000", false), "1px solid #000");
+        assert_eq!(
+            Escape::Css.apply("'Fira Code', monospace", false),
+            "'Fira Code', monospace"
+        );
+        assert_eq!(
+            Escape::Css.apply("linear-gradient(rgba(0, 0, 0, 0.5), #fff)", false),
+            "linear-gradient(rgba(0, 0, 0, 0.5), #fff)"
+        );
+        // Values that would break the declaration are quoted
+        assert_eq!(
+            Escape::Css.apply("JetBrains \"Mono\"; }", false),
+            r#""JetBrains \"Mono\"; }""#
+        );
+        assert_eq!(Escape::Css.apply("a\nb", false), r#""a\A b""#);
+        assert_eq!(Escape::Css.apply("rgb(0, 0", false), r#""rgb(0, 0""#);
+        assert_eq!(Escape::Css.apply("", false), r#""""#);
+    }
+
+    #[test]
+    fn literals() {
+        let conf: Config = serde_yaml::from_str(
+            "themes:\n  dark:\n    size: 12\n    quoted: \"12\"\n    ui: { gap: 1.5, bold: true }\n",
+        )
+        .unwrap();
+
+        let mut literals: Vec<String> = conf.literals("dark").into_iter().collect();
+        literals.sort();
+        assert_eq!(literals, vec!["size", "ui.bold", "ui.gap"]);
+    }
+
+    #[test]
+    fn floats() {
+        let conf: Config =
+            serde_yaml::from_str("themes:\n  dark: { opacity: 1.0, gap: 0.5, size: 1 }\n").unwrap();
+        let vars = conf.vars("dark").unwrap();
+
+        assert_eq!(vars["opacity"], "1.0");
+        assert_eq!(vars["gap"], "0.5");
+        assert_eq!(vars["size"], "1");
+    }
+
+    #[test]
+    fn variant_names() {
+        fn parses<T: DeserializeOwned>(names: &[&str]) -> bool {
+            names
+                .iter()
+                .all(|name| serde_yaml::from_str::<T>(name).is_ok())
+        }
+
+        assert!(parses::<WhenKeyword>(WHEN_KEYWORDS));
+        assert!(parses::<SortOrder>(SORT_ORDERS));
+        assert!(parses::<Escape>(ESCAPES));
+        assert!(parses::<Syntax>(SYNTAXES));
+        assert!(parses::<ContrastLevel>(CONTRAST_LEVELS));
+        assert!(parses::<ValueType>(VALUE_TYPES));
+    }
 }
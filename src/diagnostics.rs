@@ -0,0 +1,453 @@
+use crate::config::{
+    BlockConfig, TaggedConfig, BLOCK_OPTIONS_KEYS, CONFIG_KEYS, CONTRAST_LEVELS, ESCAPES,
+    FILE_KEYS, LINT_KEYS, OVERRIDES_KEYS, SINGLE_FILE_KEYS, SORT_ORDERS, SYNTAXES, VALUE_TYPES,
+    WHEN_KEYS, WHEN_KEYWORDS,
+};
+use regex::Regex;
+use serde_yaml::{Mapping, Value};
+
+/// Which part of configuration the document represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Document {
+    /// Whole configuration file
+    Config,
+    /// A map of themes from `themes.d`
+    Themes,
+    /// A map of files from `files.d`
+    Files,
+}
+
+/// Problem found in configuration document
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line and column of the offending key
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.position {
+            Some((line, col)) => write!(f, "line {line}, column {col}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Validates the structure of parsed document: reports unknown keys with suggestions and
+/// errors of individual file entries, which serde can't locate because `FileConfig` is untagged
+pub fn validate(contents: &str, value: &Value, document: Document) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        contents,
+        diagnostics: vec![],
+    };
+
+    match document {
+        Document::Config => checker.config(value),
+        Document::Themes => checker.themes(value, &[]),
+        Document::Files => checker.files(value, &[]),
+    }
+
+    checker.diagnostics
+}
+
+/// Finds the closest valid key for a typo
+pub fn suggest<'a>(key: &str, valid: &[&'a str]) -> Option<&'a str> {
+    valid
+        .iter()
+        .map(|v| (strsim::levenshtein(key, v), *v))
+        .filter(|(distance, v)| *distance <= (v.len() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, v)| v)
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn config(&mut self, value: &Value) {
+        let map = match self.mapping(value, &[], "configuration") {
+            Some(m) => m,
+            None => return,
+        };
+
+        self.keys(map, &[], CONFIG_KEYS, CONFIG_KEYS, "configuration");
+
+        if let Some(themes) = map.get(&Value::from("themes")) {
+            self.themes(themes, &["themes"]);
+        }
+        if let Some(files) = map.get(&Value::from("files")) {
+            self.files(files, &["files"]);
+        }
+        for section in ["profiles", "hosts"] {
+            if let Some(value) = map.get(&Value::from(section)) {
+                self.overrides(value, section);
+            }
+        }
+        if let Some(lint) = map.get(&Value::from("lint")) {
+            if let Some(lint) = self.mapping(lint, &["lint"], "`lint`") {
+                self.keys(lint, &["lint"], LINT_KEYS, LINT_KEYS, "`lint`");
+                self.variant(lint, "level", &["lint"], CONTRAST_LEVELS, "`lint`");
+            }
+        }
+        if let Some(schema) = map.get(&Value::from("schema")) {
+            if let Some(schema) = self.mapping(schema, &["schema"], "`schema`") {
+                for (key, _) in schema {
+                    self.variant(schema, &key_name(key), &["schema"], VALUE_TYPES, "`schema`");
+                }
+            }
+        }
+    }
+
+    fn overrides(&mut self, value: &Value, section: &str) {
+        let sections = match self.mapping(value, &[section], &format!("`{section}`")) {
+            Some(m) => m,
+            None => return,
+        };
+
+        for (name, overrides) in sections {
+            let name = key_name(name);
+            let path = [section, name.as_str()];
+            let what = format!("`{section}.{name}`");
+
+            let map = match self.mapping(overrides, &path, &what) {
+                Some(m) => m,
+                None => continue,
+            };
+            self.keys(map, &path, OVERRIDES_KEYS, OVERRIDES_KEYS, &what);
+
+            if let Some(files) = map.get(&Value::from("files")) {
+                self.files(files, &[section, name.as_str(), "files"]);
+            }
+            if let Some(themes) = map.get(&Value::from("themes")) {
+                self.themes(themes, &[section, name.as_str(), "themes"]);
+            }
+            if let Some(vars) = map.get(&Value::from("vars")) {
+                let vars_path = [section, name.as_str(), "vars"];
+                if let Some(vars) = self.mapping(vars, &vars_path, &what) {
+                    self.theme_vars(vars, &vars_path, &what);
+                }
+            }
+        }
+    }
+
+    fn themes(&mut self, value: &Value, path: &[&str]) {
+        let themes = match self.mapping(value, path, "themes") {
+            Some(m) => m,
+            None => return,
+        };
+
+        for (name, theme) in themes {
+            let name = key_name(name);
+            let theme_path = [path, &[name.as_str()]].concat();
+
+            if let Some(vars) = self.mapping(theme, &theme_path, &format!("theme `{name}`")) {
+                self.theme_vars(vars, &theme_path, &name);
+            }
+        }
+    }
+
+    fn theme_vars(&mut self, vars: &Mapping, path: &[&str], theme: &str) {
+        for (key, value) in vars {
+            let key = key_name(key);
+            let var_path = [path, &[key.as_str()]].concat();
+
+            match value {
+                Value::Mapping(group) => self.theme_vars(group, &var_path, theme),
+                Value::Bool(_) | Value::Number(_) | Value::String(_) => (),
+                _ => self.report(
+                    &var_path,
+                    format!("theme `{theme}`: value of `{key}` should be a string, a number, a boolean or a group of variables"),
+                ),
+            }
+        }
+    }
+
+    fn files(&mut self, value: &Value, path: &[&str]) {
+        let files = match self.mapping(value, path, "files") {
+            Some(m) => m,
+            None => return,
+        };
+
+        for (name, file) in files {
+            let name = key_name(name);
+            let file_path = [path, &[name.as_str()]].concat();
+            let what = format!("file `{name}`");
+
+            let map = match self.mapping(file, &file_path, &what) {
+                Some(m) => m,
+                None => continue,
+            };
+
+            let multi = map.contains_key(&Value::from("blocks"));
+            let valid: Vec<&str> = if multi {
+                [FILE_KEYS, &["blocks"]].concat()
+            } else {
+                [FILE_KEYS, SINGLE_FILE_KEYS, BLOCK_OPTIONS_KEYS].concat()
+            };
+            // Suggest keys of both variants, so typo in `blocks` is not left without a hint
+            let known = [FILE_KEYS, SINGLE_FILE_KEYS, BLOCK_OPTIONS_KEYS, &["blocks"]].concat();
+            let reported = self.diagnostics.len();
+            self.keys(map, &file_path, &valid, &known, &what);
+            self.variants(map, &file_path, &what);
+            self.variant(map, "syntax", &file_path, SYNTAXES, &what);
+
+            // Conditions are untagged, so serde would take unknown ones for no conditions
+            match map.get(&Value::from("when")) {
+                Some(Value::Mapping(when)) => {
+                    let when_path = [file_path.as_slice(), &["when"]].concat();
+                    self.keys(
+                        when,
+                        &when_path,
+                        WHEN_KEYS,
+                        WHEN_KEYS,
+                        &format!("`when` of {what}"),
+                    );
+                }
+                Some(_) => self.variant(map, "when", &file_path, WHEN_KEYWORDS, &what),
+                None => (),
+            }
+
+            if let Some(blocks) = map.get(&Value::from("blocks")) {
+                let blocks_path = [file_path.as_slice(), &["blocks"]].concat();
+                if let Some(blocks) = self.mapping(blocks, &blocks_path, &what) {
+                    for (tag, block) in blocks {
+                        let tag = key_name(tag);
+                        let block_path = [blocks_path.as_slice(), &[tag.as_str()]].concat();
+                        let what = format!("block `{tag}` of file `{name}`");
+
+                        if let Some(block) = self.mapping(block, &block_path, &what) {
+                            self.keys(
+                                block,
+                                &block_path,
+                                BLOCK_OPTIONS_KEYS,
+                                BLOCK_OPTIONS_KEYS,
+                                &what,
+                            );
+                            self.variants(block, &block_path, &what);
+                        }
+                    }
+                }
+            }
+
+            // Deserializing specific variant gives more precise message than untagged enum does,
+            // but it can't point at the key, so it's only used for errors not reported above
+            let result = if multi {
+                serde_yaml::from_value::<TaggedConfig>(file.clone()).map(|_| ())
+            } else {
+                serde_yaml::from_value::<BlockConfig>(file.clone()).map(|_| ())
+            };
+            if let Err(e) = result {
+                if self.diagnostics.len() == reported {
+                    self.report(&file_path, format!("{what}: {e}"));
+                }
+            }
+        }
+    }
+
+    /// Reports keys of the map that are not in `valid` list, suggesting the closest `known` key
+    fn keys(&mut self, map: &Mapping, path: &[&str], valid: &[&str], known: &[&str], what: &str) {
+        for (key, _) in map {
+            let key = key_name(key);
+            if valid.contains(&key.as_str()) {
+                continue;
+            }
+
+            let mut message = format!("unknown key `{key}` in {what}");
+            if let Some(s) = suggest(&key, known) {
+                message.push_str(&format!(", did you mean `{s}`?"));
+            }
+
+            self.report(&[path, &[key.as_str()]].concat(), message);
+        }
+    }
+
+    /// Checks values of `BlockOptions` that must be one of known names
+    fn variants(&mut self, map: &Mapping, path: &[&str], what: &str) {
+        self.variant(map, "sort", path, SORT_ORDERS, what);
+        self.variant(map, "escape", path, ESCAPES, what);
+    }
+
+    /// Reports value of the `key` that is not one of `valid` names, suggesting the closest one
+    fn variant(&mut self, map: &Mapping, key: &str, path: &[&str], valid: &[&str], what: &str) {
+        let value = match map.get(&Value::from(key)) {
+            Some(Value::Null) | None => return,
+            Some(v) => v,
+        };
+        let path = [path, &[key]].concat();
+
+        let message = match value {
+            Value::String(s) if valid.contains(&s.as_str()) => return,
+            Value::String(s) => match suggest(s, valid) {
+                Some(v) => format!("unknown value `{s}` of `{key}` in {what}, did you mean `{v}`?"),
+                None => format!(
+                    "unknown value `{s}` of `{key}` in {what}, expected one of: {}",
+                    valid.join(", ")
+                ),
+            },
+            _ => format!("`{key}` in {what} should be one of: {}", valid.join(", ")),
+        };
+        self.report(&path, message);
+    }
+
+    fn mapping<'v>(&mut self, value: &'v Value, path: &[&str], what: &str) -> Option<&'v Mapping> {
+        match value {
+            Value::Mapping(m) => Some(m),
+            _ => {
+                self.report(path, format!("{what} should be a map"));
+                None
+            }
+        }
+    }
+
+    fn report(&mut self, path: &[&str], message: String) {
+        self.diagnostics.push(Diagnostic {
+            position: locate(self.contents, path),
+            message,
+        });
+    }
+}
+
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_start_matches("---")
+            .trim()
+            .to_owned(),
+    }
+}
+
+/// Finds position of the key by searching every segment of its path after the previous one.
+/// This is an approximation, but it works well for YAML, TOML and JSON documents
+fn locate(contents: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let mut offset = 0;
+
+    for segment in path {
+        let re = Regex::new(&format!(
+            r#"(^|[\s{{,\[."'])["']?{}["']?\s*[:=.\]]"#,
+            regex::escape(segment)
+        ))
+        .ok()?;
+
+        let m = re.captures(&contents[offset..])?;
+        offset += m.get(1).map(|g| g.end()).unwrap_or(0);
+    }
+
+    if path.is_empty() {
+        return None;
+    }
+
+    let line = contents[..offset].matches('\n').count() + 1;
+    let line_start = contents[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = contents[line_start..offset].chars().count() + 1;
+
+    Some((line, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{suggest, validate, Document};
+
+    fn messages(contents: &str) -> Vec<String> {
+        let value = serde_yaml::from_str(contents).unwrap();
+        validate(contents, &value, Document::Config)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let contents = r##"themes:
+  dark:
+    background: "#000000"
+files:
+  i3:
+    path: "/"
+    commnet: ";"
+  tags:
+    path: "/"
+    blocks:
+      one:
+        fromat: "<key> <value>"
+"##;
+
+        assert_eq!(
+            messages(contents),
+            vec![
+                "line 7, column 5: unknown key `commnet` in file `i3`, did you mean `comment`?",
+                "line 12, column 9: unknown key `fromat` in block `one` of file `tags`, did you mean `format`?"
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_typo() {
+        let contents = "files:\n  tags:\n    path: \"/\"\n    blokcs:\n      one: {}\n";
+
+        assert_eq!(
+            messages(contents),
+            vec!["line 4, column 5: unknown key `blokcs` in file `tags`, did you mean `blocks`?"]
+        );
+    }
+
+    #[test]
+    fn when_typo() {
+        let contents = "files:\n  i3:\n    path: \"/\"\n    when:\n      enn: DISPLAY\n";
+
+        assert_eq!(
+            messages(contents),
+            vec!["line 5, column 7: unknown key `enn` in `when` of file `i3`, did you mean `env`?"]
+        );
+    }
+
+    #[test]
+    fn unknown_values() {
+        let contents = r##"schema:
+  bg: colour
+lint:
+  level: A
+files:
+  i3:
+    path: "/"
+    sort: alphabetical
+    when: exist
+  tags:
+    path: "/"
+    blocks:
+      one:
+        escape: jsno
+"##;
+
+        assert_eq!(
+            messages(contents),
+            vec![
+                "line 8, column 5: unknown value `alphabetical` of `sort` in file `i3`, expected one of: declared, alpha, natural",
+                "line 9, column 5: unknown value `exist` of `when` in file `i3`, did you mean `exists`?",
+                "line 14, column 9: unknown value `jsno` of `escape` in block `one` of file `tags`, did you mean `json`?",
+                "line 4, column 3: unknown value `A` of `level` in `lint`, did you mean `AA`?",
+                "line 2, column 3: unknown value `colour` of `bg` in `schema`, did you mean `color`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn file_errors() {
+        let contents = "themes: {}\nfiles:\n  i3:\n    comment: \";\"\n";
+
+        assert_eq!(
+            messages(contents),
+            vec!["line 3, column 3: file `i3`: missing field `path`"]
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggest("blokcs", &["path", "blocks"]), Some("blocks"));
+        assert_eq!(suggest("background", &["path", "blocks"]), None);
+    }
+}
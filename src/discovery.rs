@@ -0,0 +1,156 @@
+use crate::utils::expand_path;
+use std::{env, path::PathBuf};
+
+/// Names of the configuration file that are looked up inside `themer` directories
+const FILE_NAMES: &[&str] = &["config.yml", "config.yaml", "config.toml", "config.json"];
+
+/// Chosen configuration file and the reason it was chosen
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigLocation {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Finds configuration file. The first match wins:
+/// `--config` flag, `THEMER_CONFIG` variable, `$XDG_CONFIG_HOME/themer/` (`~/.config/themer/`)
+/// and every directory of `$XDG_CONFIG_DIRS` (`/etc/xdg`) for system-wide defaults
+pub fn discover(flag: Option<&str>) -> Result<ConfigLocation, String> {
+    discover_with(flag, |name| env::var(name).ok().filter(|v| !v.is_empty()))
+}
+
+/// Path where a new configuration file should be created: `--config` flag, `THEMER_CONFIG`
+/// variable or `$XDG_CONFIG_HOME/themer/config.yml`
+pub fn init_path(flag: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(path) = flag {
+        return Ok(PathBuf::from(expand_path(path)?));
+    }
+
+    match env::var("THEMER_CONFIG").ok().filter(|v| !v.is_empty()) {
+        Some(path) => Ok(PathBuf::from(expand_path(&path)?)),
+        None => {
+            let config_home = match env::var("XDG_CONFIG_HOME").ok().filter(|v| !v.is_empty()) {
+                Some(dir) => dir,
+                None => expand_path("~/.config")?,
+            };
+            Ok(PathBuf::from(config_home)
+                .join("themer")
+                .join(FILE_NAMES[0]))
+        }
+    }
+}
+
+fn discover_with(
+    flag: Option<&str>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<ConfigLocation, String> {
+    if let Some(path) = flag {
+        return Ok(ConfigLocation {
+            path: PathBuf::from(expand_path(path)?),
+            reason: "set with --config flag".to_owned(),
+        });
+    }
+
+    if let Some(path) = var("THEMER_CONFIG") {
+        return Ok(ConfigLocation {
+            path: PathBuf::from(expand_path(&path)?),
+            reason: "set with THEMER_CONFIG environment variable".to_owned(),
+        });
+    }
+
+    // Without $HOME there's no user configuration, system-wide one may still be found
+    let config_home = match var("XDG_CONFIG_HOME") {
+        Some(dir) => Some((PathBuf::from(dir), "found in $XDG_CONFIG_HOME")),
+        None => var("HOME").map(|home| (PathBuf::from(home).join(".config"), "found in ~/.config")),
+    };
+    let config_dirs = var("XDG_CONFIG_DIRS").unwrap_or_else(|| "/etc/xdg".to_owned());
+    let system = config_dirs
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(|d| (PathBuf::from(d), "found in $XDG_CONFIG_DIRS"));
+
+    let mut searched = vec![];
+    for (dir, reason) in config_home.into_iter().chain(system) {
+        for name in FILE_NAMES {
+            let path = dir.join("themer").join(name);
+            if path.is_file() {
+                return Ok(ConfigLocation {
+                    path,
+                    reason: reason.to_owned(),
+                });
+            }
+        }
+        searched.push(dir.join("themer").display().to_string());
+    }
+
+    Err(format!(
+        "Failed to find Themer configuration file, searched in: {}",
+        searched.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::discover_with;
+    use std::path::PathBuf;
+
+    fn vars(name: &str) -> Option<String> {
+        match name {
+            "XDG_CONFIG_HOME" => Some("./test-configs/xdg/empty".to_owned()),
+            "XDG_CONFIG_DIRS" => {
+                Some("./test-configs/xdg/missing:./test-configs/xdg/system".to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let flag = discover_with(Some("/from/flag.yml"), vars).unwrap();
+        assert_eq!(flag.path, PathBuf::from("/from/flag.yml"));
+
+        let env = discover_with(None, |name| match name {
+            "THEMER_CONFIG" => Some("/from/env.toml".to_owned()),
+            other => vars(other),
+        })
+        .unwrap();
+        assert_eq!(env.path, PathBuf::from("/from/env.toml"));
+    }
+
+    #[test]
+    fn xdg_dirs() {
+        let found = discover_with(None, vars).unwrap();
+
+        assert_eq!(
+            found.path,
+            PathBuf::from("./test-configs/xdg/system/themer/config.toml")
+        );
+        assert_eq!(found.reason, "found in $XDG_CONFIG_DIRS");
+
+        let err = discover_with(None, |name| match name {
+            "XDG_CONFIG_DIRS" => Some("./test-configs/xdg/missing".to_owned()),
+            other => vars(other),
+        })
+        .unwrap_err();
+        assert!(err.contains("./test-configs/xdg/missing/themer"));
+    }
+
+    #[test]
+    fn no_home() {
+        let found = discover_with(None, |name| match name {
+            "XDG_CONFIG_DIRS" => Some("./test-configs/xdg/system".to_owned()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(found.reason, "found in $XDG_CONFIG_DIRS");
+
+        let home = discover_with(None, |name| match name {
+            "HOME" => Some("./test-configs/xdg/empty".to_owned()),
+            "XDG_CONFIG_DIRS" => Some("./test-configs/xdg/missing".to_owned()),
+            _ => None,
+        })
+        .unwrap_err();
+        assert!(home.ends_with(
+            "./test-configs/xdg/empty/.config/themer, ./test-configs/xdg/missing/themer"
+        ));
+    }
+}
@@ -0,0 +1,606 @@
+use crate::{
+    block::BlockGenerator,
+    config::{BlockConfig, FileConfig, Paths, ThemeVars},
+};
+use colored::Colorize;
+use serde_yaml::{Mapping, Value};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Application Themer knows how to configure
+struct App {
+    name: &'static str,
+    /// Candidate paths relative to home directory, the first existing one is used
+    paths: &'static [&'static str],
+    comment: &'static str,
+    closing_comment: Option<&'static str>,
+    format: &'static str,
+    /// Headers of the (nested) section variables must be placed in. The block is put at the
+    /// start of that section, or appended to the end of the file when there are none
+    section: &'static [&'static str],
+}
+
+const APPS: &[App] = &[
+    App {
+        name: "alacritty",
+        paths: &[".config/alacritty/alacritty.toml"],
+        comment: "#",
+        closing_comment: None,
+        format: "<key> = \"<value>\"",
+        section: &["[colors.primary]"],
+    },
+    App {
+        name: "alacritty",
+        paths: &[".config/alacritty/alacritty.yml"],
+        comment: "#",
+        closing_comment: None,
+        format: "<key>: \"<value>\"",
+        section: &["colors:", "primary:"],
+    },
+    App {
+        name: "kitty",
+        paths: &[".config/kitty/kitty.conf"],
+        comment: "#",
+        closing_comment: None,
+        format: "<key> <value>",
+        section: &[],
+    },
+    App {
+        name: "i3",
+        paths: &[".config/i3/config", ".i3/config"],
+        comment: "#",
+        closing_comment: None,
+        format: "set $<key> <value>",
+        section: &[],
+    },
+    App {
+        name: "sway",
+        paths: &[".config/sway/config"],
+        comment: "#",
+        closing_comment: None,
+        format: "set $<key> <value>",
+        section: &[],
+    },
+    App {
+        name: "polybar",
+        paths: &[".config/polybar/config.ini", ".config/polybar/config"],
+        comment: ";",
+        closing_comment: None,
+        format: "<key> = <value>",
+        section: &["[colors]"],
+    },
+    App {
+        name: "waybar",
+        paths: &[".config/waybar/style.css"],
+        comment: "/*",
+        closing_comment: Some("*/"),
+        format: "@define-color <key> <value>;",
+        section: &[],
+    },
+    App {
+        name: "rofi",
+        paths: &[".config/rofi/config.rasi"],
+        comment: "/*",
+        closing_comment: Some("*/"),
+        format: "<key>: <value>;",
+        section: &["* {"],
+    },
+    App {
+        name: "dunst",
+        paths: &[".config/dunst/dunstrc"],
+        comment: "#",
+        closing_comment: None,
+        format: "<key> = \"<value>\"",
+        section: &["[urgency_normal]"],
+    },
+    App {
+        name: "foot",
+        paths: &[".config/foot/foot.ini"],
+        comment: "#",
+        closing_comment: None,
+        format: "<key>=<value>",
+        section: &["[colors]"],
+    },
+    App {
+        name: "xresources",
+        paths: &[".Xresources"],
+        comment: "!",
+        closing_comment: None,
+        format: "*.<key>: <value>",
+        section: &[],
+    },
+];
+
+/// Variables of the starter themes
+const THEME_VARS: [&str; 2] = ["background", "foreground"];
+
+/// Where Themer block is added to the detected file
+#[derive(Debug, PartialEq)]
+enum Placement {
+    /// Appended to the end of the file
+    End,
+    /// Inserted before the line with given index, at the start of the app section
+    Section { line: usize, indent: String },
+    /// Block can't be added without breaking the file, holds the reason
+    Manual(String),
+}
+
+/// Finds the start of the app section in the file. The block is placed there only when the
+/// section exists and doesn't set variables of the starter themes already, since adding the
+/// section or its keys again would make the file invalid
+fn placement(app: &App, contents: &str) -> Placement {
+    if app.section.is_empty() {
+        return Placement::End;
+    }
+
+    let lines: Vec<&str> = contents.lines().collect();
+    fn indent(line: &str) -> &str {
+        &line[..line.len() - line.trim_start().len()]
+    }
+    let is_content = |line: &str| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with(app.comment)
+    };
+
+    // Lines of the section found so far, narrowed with every nested header
+    let (mut start, mut end) = (0, lines.len());
+    let mut header = "";
+    for name in app.section {
+        let found = match (start..end).find(|&i| lines[i].trim() == *name) {
+            Some(i) => i,
+            None => {
+                return Placement::Manual(format!("section `{}` is missing", app.section.join(" ")))
+            }
+        };
+
+        header = lines[found];
+        start = found + 1;
+        end = (start..end)
+            .find(|&i| {
+                let line = lines[i];
+                is_content(line)
+                    && match name.starts_with('[') {
+                        true => line.trim_start().starts_with('['),
+                        false => indent(line).len() <= indent(header).len(),
+                    }
+            })
+            .unwrap_or(end);
+    }
+
+    let body: Vec<&str> = lines[start..end]
+        .iter()
+        .copied()
+        .filter(|l| is_content(l))
+        .collect();
+    for line in &body {
+        let key = line.split(['=', ':']).next().unwrap_or_default().trim();
+        if THEME_VARS.contains(&key) {
+            return Placement::Manual(format!(
+                "section `{}` already sets `{key}`",
+                app.section.join(" ")
+            ));
+        }
+    }
+
+    let indent = match body.first() {
+        Some(line) if indent(line).len() > indent(header).len() => indent(line).to_owned(),
+        _ if header.ends_with(':') || header.ends_with('{') => format!("{}  ", indent(header)),
+        _ => indent(header).to_owned(),
+    };
+
+    Placement::Section {
+        line: start,
+        indent,
+    }
+}
+
+/// Configuration file of a detected application
+struct Detected {
+    app: &'static App,
+    /// Path relative to home directory
+    path: &'static str,
+    absolute: PathBuf,
+    placement: Placement,
+}
+
+impl Detected {
+    fn block_config(&self) -> BlockConfig {
+        BlockConfig {
+            path: Paths::Single(self.absolute.display().to_string()),
+            comment: Some(self.app.comment.to_owned()),
+            closing_comment: self.app.closing_comment.map(str::to_owned),
+            ..Default::default()
+        }
+    }
+}
+
+/// Finds configuration files of known applications in the home directory
+fn detect(home: &Path) -> Vec<Detected> {
+    let mut detected: Vec<Detected> = vec![];
+
+    for app in APPS {
+        // Same application may be listed several times for different config formats
+        if detected.iter().any(|d| d.app.name == app.name) {
+            continue;
+        }
+
+        if let Some(path) = app.paths.iter().find(|p| home.join(p).is_file()) {
+            let absolute = home.join(path);
+            // Unreadable file is reported when the block is added
+            let placement = fs::read_to_string(&absolute)
+                .map(|contents| placement(app, &contents))
+                .unwrap_or(Placement::End);
+
+            detected.push(Detected {
+                app,
+                path,
+                absolute,
+                placement,
+            });
+        }
+    }
+
+    detected
+}
+
+/// Documented example configuration, the starter file keeps its comments
+const EXAMPLE: &str = include_str!("../assets/config.yml");
+
+/// Produces contents of the starter configuration file: comments of the example configuration
+/// with its `themes` and `files` sections replaced
+fn render(detected: &[Detected]) -> String {
+    let string = |s: &str| Value::String(s.to_owned());
+    let theme = |values: [&str; 2]| {
+        let mut theme = Mapping::new();
+        for (key, value) in THEME_VARS.iter().zip(values) {
+            theme.insert(string(key), string(value));
+        }
+        Value::Mapping(theme)
+    };
+
+    let mut themes = Mapping::new();
+    themes.insert(string("dark"), theme(["#1d2021", "#ebdbb2"]));
+    themes.insert(string("light"), theme(["#fbf1c7", "#3c3836"]));
+
+    let mut files = Mapping::new();
+    for d in detected {
+        let mut file = Mapping::new();
+        file.insert(string("path"), string(&format!("~/{}", d.path)));
+        file.insert(string("comment"), string(d.app.comment));
+        if let Some(closing) = d.app.closing_comment {
+            file.insert(string("closing_comment"), string(closing));
+        }
+        file.insert(string("format"), string(d.app.format));
+        files.insert(string(d.app.name), Value::Mapping(file));
+    }
+
+    let mut config = String::new();
+    // Body of the example section that is being replaced
+    let mut replaced = false;
+    for line in EXAMPLE.lines() {
+        let section = match line {
+            "themes:" => Some(("themes", &themes)),
+            "files:" => Some(("files", &files)),
+            _ => None,
+        };
+
+        if let Some((name, value)) = section {
+            let mut map = Mapping::new();
+            map.insert(string(name), Value::Mapping(value.clone()));
+            let yaml = serde_yaml::to_string(&map).unwrap_or_default();
+            config.push_str(yaml.trim_start_matches("---\n"));
+            replaced = true;
+            continue;
+        }
+
+        if replaced {
+            // Section ends with the first comment or key that is not indented
+            if line.is_empty() || line.starts_with(' ') {
+                continue;
+            }
+            replaced = false;
+            config.push('\n');
+        }
+
+        config.push_str(line);
+        config.push('\n');
+    }
+
+    config
+}
+
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).ok();
+
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Adds empty Themer block to the file, so it can be updated by `themer set`
+fn add_block(detected: &Detected) -> Result<(), String> {
+    let path = &detected.absolute;
+    let mut contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file {}: {e}", path.display()))?;
+
+    let vars = ThemeVars::new();
+    let gen = BlockGenerator::new(
+        String::new(),
+        &vars,
+        FileConfig::Single(detected.block_config()),
+    );
+    let (start, _) = gen.get_tags();
+    if contents.contains(&start) {
+        return Ok(());
+    }
+
+    let (line, block) = match &detected.placement {
+        Placement::Section { line, indent } => (*line, gen.wrap_indented("", indent)),
+        _ => (contents.lines().count(), gen.wrap("")),
+    };
+    let newline = match contents.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let block = (block + "\n").replace('\n', newline);
+
+    // Byte offset of the line the block is inserted before
+    let offset: usize = contents
+        .split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum();
+    if offset == contents.len() {
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push_str(newline);
+        }
+        contents.push_str(&block);
+    } else {
+        contents.insert_str(offset, &block);
+    }
+
+    fs::write(path, contents).map_err(|e| format!("Failed to write file {}: {e}", path.display()))
+}
+
+/// Creates starter configuration file at `target`.
+/// With `detect`, adds entries for configuration files of known applications and offers to add
+/// Themer blocks to them (`yes` accepts every offer)
+pub fn run(target: &Path, detect_apps: bool, force: bool, yes: bool) -> Result<(), String> {
+    if target.exists() && !force {
+        return Err(format!(
+            "Configuration file '{}' already exists, use --force to overwrite it",
+            target.display()
+        ));
+    }
+
+    let detected = if detect_apps {
+        let home = std::env::var("HOME").map_err(|_| "$HOME is not set".to_owned())?;
+        detect(Path::new(&home))
+    } else {
+        vec![]
+    };
+
+    if detect_apps {
+        println!("{}", "Detected applications:".purple());
+        if detected.is_empty() {
+            println!("  None");
+        }
+        for d in &detected {
+            println!("  - {} ({})", d.app.name.blue(), d.absolute.display());
+        }
+    }
+
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create directory {}: {e}", dir.display()))?;
+    }
+    fs::write(target, render(&detected))
+        .map_err(|e| format!("Failed to write file {}: {e}", target.display()))?;
+    println!("{} {}", "Created".green(), target.display());
+
+    for d in &detected {
+        let question = format!("Add Themer block to {}?", d.absolute.display());
+        if let Placement::Manual(reason) = &d.placement {
+            println!(
+                " {} Themer block can't be added to {} automatically: {reason}",
+                "!".yellow(),
+                d.absolute.display()
+            );
+        }
+
+        if !matches!(d.placement, Placement::Manual(_)) && (yes || confirm(&question)) {
+            add_block(d)?;
+            println!("{} {}", "Updated".green(), d.absolute.display());
+        } else {
+            println!(
+                " {} Add `{}` block to {} manually{}",
+                "?".blue(),
+                BlockGenerator::new(
+                    String::new(),
+                    &ThemeVars::new(),
+                    FileConfig::Single(d.block_config())
+                )
+                .get_tags()
+                .0,
+                d.absolute.display(),
+                match d.app.section.is_empty() {
+                    true => String::new(),
+                    false => format!(", inside `{}` section", d.app.section.join(" ")),
+                }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_block, detect, placement, render, run, Detected, Placement, APPS};
+    use crate::{
+        block::BlockGenerator,
+        config::{Config, FileConfig, ThemeVars},
+        updates::UpdatesGenerator,
+    };
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    /// Empty directory for files written by the test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("themer-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn detect_apps() {
+        let detected = detect(Path::new("./test-configs/home"));
+        let names: Vec<&str> = detected.iter().map(|d| d.app.name).collect();
+
+        assert_eq!(names, vec!["kitty", "i3", "waybar"]);
+        assert_eq!(detected[1].path, ".i3/config");
+    }
+
+    #[test]
+    fn render_config() {
+        let detected = detect(Path::new("./test-configs/home"));
+        let rendered = render(&detected);
+        let conf: Config = serde_yaml::from_str(&rendered).unwrap();
+
+        assert_eq!(conf.themes.len(), 2);
+        assert_eq!(conf.files.len(), 3);
+        assert_eq!(
+            conf.files.get("waybar").unwrap().flatten()[0].block.format,
+            "@define-color <key> <value>;"
+        );
+        // Comments of the example configuration are kept
+        assert!(rendered.contains("\n# Uncomment to specify shell command that will run after"));
+        assert!(rendered.contains("\n\n# Uncomment to change configuration on specific machines"));
+
+        let conf: Config = serde_yaml::from_str(&render(&[])).unwrap();
+        assert!(conf.files.is_empty());
+    }
+
+    #[test]
+    fn create_config() {
+        let target = scratch("init").join("themer/config.yml");
+
+        run(&target, false, false, true).unwrap();
+        let conf: Config = serde_yaml::from_str(&fs::read_to_string(&target).unwrap()).unwrap();
+        assert_eq!(conf.themes.len(), 2);
+
+        assert!(run(&target, false, false, true)
+            .unwrap_err()
+            .ends_with("already exists, use --force to overwrite it"));
+        assert!(run(&target, false, true, true).is_ok());
+    }
+
+    /// Writes the file of the app and detects it like `init --detect` does
+    fn detected(name: &str, path: &'static str, contents: &str) -> Detected {
+        let app = APPS.iter().find(|a| a.paths.contains(&path)).unwrap();
+        let absolute = scratch(name).join(path.rsplit('/').next().unwrap());
+        fs::write(&absolute, contents).unwrap();
+
+        Detected {
+            app,
+            path,
+            absolute,
+            placement: placement(app, contents),
+        }
+    }
+
+    /// Contents of the file after `themer set` with a starter theme
+    fn set(detected: &Detected) -> String {
+        let mut config = detected.block_config();
+        config.block.format = detected.app.format.to_owned();
+        let config = FileConfig::Single(config);
+
+        let vars = ThemeVars::from([
+            ("background".to_owned(), "#1d2021".to_owned()),
+            ("foreground".to_owned(), "#ebdbb2".to_owned()),
+        ]);
+        let gen = BlockGenerator::new("dark".to_owned(), &vars, config.clone());
+        let bytes = UpdatesGenerator::new(gen).generate(&config).unwrap();
+
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn sections() {
+        let toml = "[colors.primary]\ncursor = \"#ffffff\"\n\n[colors.normal]\nred = \"#ff0000\"\n";
+        let d = detected("section-toml", ".config/alacritty/alacritty.toml", toml);
+        add_block(&d).unwrap();
+        let updated = set(&d);
+        assert_eq!(
+            updated,
+            "[colors.primary]\n# THEMER\nbackground = \"#1d2021\"\nforeground = \"#ebdbb2\"\n\
+             # THEMER_END\ncursor = \"#ffffff\"\n\n[colors.normal]\nred = \"#ff0000\"\n"
+        );
+        toml::from_str::<toml::Value>(&updated).unwrap();
+
+        let yaml = "font:\n  size: 10\ncolors:\n    normal:\n        red: '#ff0000'\n    primary:\n        cursor: '#ffffff'\n";
+        let d = detected("section-yaml", ".config/alacritty/alacritty.yml", yaml);
+        assert_eq!(
+            d.placement,
+            Placement::Section {
+                line: 6,
+                indent: "        ".to_owned()
+            }
+        );
+        add_block(&d).unwrap();
+        let updated: serde_yaml::Value = serde_yaml::from_str(&set(&d)).unwrap();
+        assert_eq!(updated["colors"]["primary"]["background"], "#1d2021");
+        assert_eq!(updated["colors"]["primary"]["cursor"], "#ffffff");
+        assert_eq!(updated["colors"]["normal"]["red"], "#ff0000");
+
+        let rasi = "configuration {}\n* {\n    font: \"mono 10\";\n}\n";
+        let d = detected("section-rasi", ".config/rofi/config.rasi", rasi);
+        add_block(&d).unwrap();
+        assert!(set(&d)
+            .starts_with("configuration {}\n* {\n    /* THEMER */\n    background: #1d2021;\n"));
+    }
+
+    #[test]
+    fn manual_sections() {
+        // Section that is missing or sets the same variables can't be changed safely
+        for contents in [
+            "[colors.primary]\nbackground = \"#000\"\n",
+            "[colors.normal]\nred = \"#ff0000\"\n",
+        ] {
+            let d = detected("manual", ".config/alacritty/alacritty.toml", contents);
+            assert!(matches!(d.placement, Placement::Manual(_)));
+        }
+        let d = detected(
+            "manual",
+            ".config/alacritty/alacritty.yml",
+            "colors:\n  normal:\n    red: '#ff0000'\nprimary:\n  cursor: '#ffffff'\n",
+        );
+        assert_eq!(
+            d.placement,
+            Placement::Manual("section `colors: primary:` is missing".to_owned())
+        );
+    }
+
+    #[test]
+    fn append_block() {
+        let detected = detected("add-block", ".config/waybar/style.css", "window {}");
+        let absolute = &detected.absolute;
+
+        // Block is not added twice
+        add_block(&detected).unwrap();
+        add_block(&detected).unwrap();
+        assert_eq!(
+            fs::read_to_string(absolute).unwrap(),
+            "window {}\n/* THEMER */\n\n/* THEMER_END */\n"
+        );
+    }
+}
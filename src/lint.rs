@@ -0,0 +1,105 @@
+use crate::{
+    color::Color,
+    config::{Config, ThemeVars},
+};
+use colored::Colorize;
+
+/// Checks contrast of configured variable pairs in every theme.
+/// Returns `false` if at least one pair does not satisfy the required level
+pub fn run(config: &Config) -> bool {
+    let lint = config.lint.clone().unwrap_or_default();
+    let level = lint.level;
+    let threshold = level.threshold();
+    let pairs = lint.all_pairs();
+    let mut passed = true;
+
+    for (name, vars) in config.all_vars() {
+        println!("{}", name.blue());
+
+        for [fg_key, bg_key] in &pairs {
+            let ratio = match contrast(&vars, fg_key, bg_key) {
+                Ok(ratio) => ratio,
+                Err(reason) => {
+                    println!("  {} {fg_key}/{bg_key} [{reason}]", "skip".yellow());
+                    continue;
+                }
+            };
+
+            if ratio >= threshold {
+                println!("  {} {fg_key}/{bg_key} {ratio:.2}:1", "ok".green());
+            } else {
+                passed = false;
+                println!(
+                    "  {} {fg_key}/{bg_key} {ratio:.2}:1 [{}]",
+                    "err".red(),
+                    format!("{} requires at least {threshold}:1", level.name()).red()
+                );
+            }
+        }
+    }
+
+    passed
+}
+
+/// Contrast ratio of the pair, or the reason it can't be checked
+fn contrast(vars: &ThemeVars, fg_key: &str, bg_key: &str) -> Result<f64, &'static str> {
+    let (fg, bg) = match (vars.get(fg_key), vars.get(bg_key)) {
+        (Some(fg), Some(bg)) => (fg, bg),
+        _ => return Err("variable is missing"),
+    };
+
+    match (Color::parse(fg), Color::parse(bg)) {
+        (Some(fg), Some(bg)) => Ok(fg.contrast(bg)),
+        _ => Err("value is not a hex color"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contrast, run};
+    use crate::config::{Config, ContrastLevel, LintConfig};
+
+    fn load(level: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            "lint:
+  level: {level}
+  pairs:
+    - [foreground, background]
+    - [accent, background]
+    - [missing, background]
+    - [name, background]
+themes:
+  gray:
+    foreground: \"#767676\"
+    background: \"#ffffff\"
+    accent: \"#000000\"
+    name: gray
+"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn levels() {
+        // 4.54:1 is enough for AA, but not for AAA
+        assert!(run(&load("AA")));
+        assert!(!run(&load("AAA")));
+    }
+
+    #[test]
+    fn pairs() {
+        let conf = load("AA");
+        let lint = conf.lint.clone().unwrap();
+        assert_eq!(lint.level, ContrastLevel::Aa);
+        // Default pair is checked once
+        assert_eq!(lint.all_pairs().len(), 4);
+        assert_eq!(LintConfig::default().all_pairs().len(), 1);
+
+        let vars = conf.vars("gray").unwrap();
+        let ratio = |fg: &str| contrast(&vars, fg, "background");
+        assert!((ratio("foreground").unwrap() - 4.54).abs() < 0.01);
+        assert!((ratio("accent").unwrap() - 21.0).abs() < 0.01);
+        assert_eq!(ratio("missing"), Err("variable is missing"));
+        assert_eq!(ratio("name"), Err("value is not a hex color"));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    config::{Config, FileConfig, Theme},
+    diagnostics::{self, Document},
+    utils::expand_path,
+};
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directories next to the main config file that are loaded automatically.
+/// Every file inside them is a map of theme (or file) names, just like `themes` and `files` sections
+const THEMES_DIR: &str = "themes.d";
+const FILES_DIR: &str = "files.d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detects format by file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yml") | Some("yaml") => Some(ConfigFormat::Yaml),
+            Some("toml") => Some(ConfigFormat::Toml),
+            Some("json") => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Loads the configuration file with everything it includes.
+///
+/// Sources are merged in this order: the main file, files from its `include` list (recursively,
+/// relative to the file that includes them), `themes.d/*.yml` and `files.d/*.yml`.
+/// Themes, files, profiles and hosts can be defined only once across all sources. `reload`,
+/// `lint` and `schema` entries are taken from the first source that defines them, so the main
+/// file always wins.
+///
+/// Format of every file is detected by its extension. `format` overrides detection for the main
+/// file and is used for included files with unknown extension, YAML is used otherwise.
+pub fn load(path: &Path, format: Option<ConfigFormat>) -> Result<Config, String> {
+    let mut loader = Loader {
+        format,
+        ..Default::default()
+    };
+    let mut config = Config::default();
+
+    let main_format = format
+        .or_else(|| ConfigFormat::from_path(path))
+        .unwrap_or(ConfigFormat::Yaml);
+    loader.merge_file(&mut config, path, main_format)?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for entry in dir_entries(&dir.join(THEMES_DIR))? {
+        let themes: IndexMap<String, Theme> =
+            parse(&entry, loader.detect(&entry), Document::Themes)?;
+        loader.merge(&mut config.themes, themes, "Theme", &entry)?;
+    }
+    for entry in dir_entries(&dir.join(FILES_DIR))? {
+        let files: IndexMap<String, FileConfig> =
+            parse(&entry, loader.detect(&entry), Document::Files)?;
+        loader.merge(&mut config.files, files, "File", &entry)?;
+    }
+
+    Ok(config)
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|_| {
+        format!(
+            "Failed to read Themer configuration file in '{}'",
+            path.display()
+        )
+    })
+}
+
+fn parse<T: DeserializeOwned>(
+    path: &Path,
+    format: ConfigFormat,
+    document: Document,
+) -> Result<T, String> {
+    let contents = read(path)?;
+    let fail = |e: String| {
+        format!(
+            "Failed to parse configuration file '{}':\n\t{e}",
+            path.display()
+        )
+    };
+
+    // Every format is parsed into the same generic value, so it's validated the same way
+    let value: Value = match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::from_str(&contents).map_err(|e| e.to_string()),
+        ConfigFormat::Json => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+    }
+    .map_err(fail)?;
+
+    let diagnostics = diagnostics::validate(&contents, &value, document);
+    if !diagnostics.is_empty() {
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        return Err(fail(messages.join("\n\t")));
+    }
+
+    serde_yaml::from_value(value).map_err(|e| fail(e.to_string()))
+}
+
+/// Lists configuration files inside the directory sorted by name. Missing directory has no files
+fn dir_entries(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory '{}': {e}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && ConfigFormat::from_path(p).is_some())
+        .collect();
+    entries.sort();
+
+    Ok(entries)
+}
+
+#[derive(Default)]
+struct Loader {
+    /// Format used for files with unknown extension
+    format: Option<ConfigFormat>,
+    /// Files that are being loaded, used to detect include cycles
+    stack: Vec<PathBuf>,
+    /// Files that were already loaded, a file shared by several includes is merged once
+    loaded: HashSet<PathBuf>,
+    /// Source file of every theme and file entry, used to report duplicates
+    sources: HashMap<String, PathBuf>,
+}
+
+impl Loader {
+    fn detect(&self, path: &Path) -> ConfigFormat {
+        ConfigFormat::from_path(path)
+            .or(self.format)
+            .unwrap_or(ConfigFormat::Yaml)
+    }
+
+    fn merge_file(
+        &mut self,
+        config: &mut Config,
+        path: &Path,
+        format: ConfigFormat,
+    ) -> Result<(), String> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.stack.contains(&canonical) {
+            return Err(format!(
+                "Configuration file '{}' includes itself",
+                path.display()
+            ));
+        }
+        if !self.loaded.insert(canonical.clone()) {
+            return Ok(());
+        }
+
+        let part: Config = parse(path, format, Document::Config)?;
+
+        self.merge(&mut config.themes, part.themes, "Theme", path)?;
+        self.merge(&mut config.files, part.files, "File", path)?;
+        self.merge(&mut config.profiles, part.profiles, "Profile", path)?;
+        self.merge(&mut config.hosts, part.hosts, "Host", path)?;
+
+        config.disable.extend(part.disable);
+        for (key, value_type) in part.schema {
+            config.schema.entry(key).or_insert(value_type);
+        }
+        if config.reload.is_none() {
+            config.reload = part.reload;
+        }
+        if config.lint.is_none() {
+            config.lint = part.lint;
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        self.stack.push(canonical);
+        for include in part.include {
+            let include = dir.join(expand_path(&include)?);
+            let format = self.detect(&include);
+            self.merge_file(config, &include, format)?;
+        }
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    fn merge<T>(
+        &mut self,
+        target: &mut BTreeMap<String, T>,
+        entries: impl IntoIterator<Item = (String, T)>,
+        kind: &str,
+        path: &Path,
+    ) -> Result<(), String> {
+        for (name, value) in entries {
+            let id = format!("{kind} `{name}`");
+
+            if let Some(prev) = self.sources.get(&id) {
+                return Err(format!(
+                    "{id} is defined both in '{}' and '{}'",
+                    prev.display(),
+                    path.display()
+                ));
+            }
+
+            self.sources.insert(id, path.to_path_buf());
+            target.insert(name, value);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, ConfigFormat};
+    use std::path::Path;
+
+    #[test]
+    fn merges_sources() {
+        let conf = load(Path::new("./test-configs/split/config.yml"), None).unwrap();
+
+        assert_eq!(
+            conf.themes.keys().collect::<Vec<_>>(),
+            vec!["dark", "included", "light"]
+        );
+        assert_eq!(
+            conf.files.keys().collect::<Vec<_>>(),
+            vec!["bar", "terminal"]
+        );
+        assert_eq!(conf.reload.as_deref(), Some("echo main"));
+    }
+
+    #[test]
+    fn duplicate_theme() {
+        let err = load(Path::new("./test-configs/split/duplicate.yml"), None).unwrap_err();
+
+        assert!(err.contains("Theme `included` is defined both in"));
+        assert!(err.contains("duplicate.yml"));
+        assert!(err.contains("include.yml"));
+    }
+
+    #[test]
+    fn shared_include() {
+        let conf = load(Path::new("./test-configs/includes/diamond.yml"), None).unwrap();
+
+        assert_eq!(
+            conf.themes.keys().collect::<Vec<_>>(),
+            vec!["left", "right", "shared"]
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let err = load(Path::new("./test-configs/includes/cycle.yml"), None).unwrap_err();
+
+        assert_eq!(
+            err,
+            "Configuration file './test-configs/includes/cycle.yml' includes itself"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let yaml = load(Path::new("./test-configs/config.yml"), None).unwrap();
+
+        for path in ["./test-configs/config.toml", "./test-configs/config.json"] {
+            let conf = load(Path::new(path), None).unwrap();
+
+            assert_eq!(conf.vars("theme"), yaml.vars("theme"));
+            assert_eq!(conf.vars("structured"), yaml.vars("structured"));
+            assert_eq!(
+                format!("{:?}", conf.files.get("tags")),
+                format!("{:?}", yaml.files.get("tags"))
+            );
+        }
+
+        let err = load(
+            Path::new("./test-configs/config.json"),
+            Some(ConfigFormat::Toml),
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Failed to parse configuration file './test-configs/config.json'"));
+    }
+}
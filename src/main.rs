@@ -1,28 +1,40 @@
 mod block;
+mod check;
+mod color;
+mod comments;
 mod config;
+mod diagnostics;
+mod discovery;
+mod init;
+mod lint;
+mod loader;
+mod preview;
+mod profiles;
+mod structured;
+mod text;
 mod updates;
 mod utils;
 
-use clap::{Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand};
 use colored::Colorize;
-use config::Config;
-use log;
 use simplelog::{ColorChoice, ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
+use std::process::exit;
 use std::process::Command;
-use std::{fs, process::exit};
-use utils::expand_tilde;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// Path to the config file
-    #[clap(
-        global = true,
-        short,
-        long,
-        default_value = "~/.config/themer/config.yml"
-    )]
-    config: String,
+    /// Path to the config file [default: $THEMER_CONFIG or ~/.config/themer/config.yml]
+    #[clap(global = true, short, long)]
+    config: Option<String>,
+
+    /// Format of the config file, detected by extension if not set
+    #[clap(global = true, long, value_enum)]
+    config_format: Option<loader::ConfigFormat>,
+
+    /// Comma-separated list of profiles to apply on top of the host overrides
+    #[clap(global = true, short, long, value_delimiter = ',')]
+    profile: Vec<String>,
 
     #[clap(subcommand)]
     command: Option<Commands>,
@@ -31,21 +43,74 @@ struct Args {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// List avaliable themes in config file
-    Themes,
+    Themes {
+        /// Show color palette of every theme
+        #[clap(parse(from_flag), long)]
+        preview: bool,
+    },
+    /// Show color palette of the theme without applying it
+    Preview {
+        /// Theme name to preview
+        #[clap(required = true, value_parser)]
+        theme: String,
+    },
     /// List avaliable files in config file
     Files {
         /// Check if config files are valid to be process by Themer
         #[clap(parse(from_flag), long)]
         check: bool,
+
+        #[clap(flatten)]
+        filter: FilesFilter,
+    },
+    /// Check themes against schema and files for references to missing variables
+    Check,
+    /// Check contrast ratio of color pairs in all themes
+    Lint,
+    /// Create a starter configuration file
+    Init {
+        /// Add entries for configuration files of known applications
+        #[clap(parse(from_flag), long)]
+        detect: bool,
+        /// Overwrite existing configuration file
+        #[clap(parse(from_flag), long)]
+        force: bool,
+        /// Add Themer blocks to detected files without asking
+        #[clap(parse(from_flag), short, long)]
+        yes: bool,
+    },
+    /// Information about configuration file
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
     },
     /// Set new theme for all of your configuration files
     Set {
         /// Theme name to set
         #[clap(required = true, value_parser)]
         theme: String,
+
+        #[clap(flatten)]
+        filter: FilesFilter,
     },
 }
 
+#[derive(ClapArgs, Debug)]
+struct FilesFilter {
+    /// Process only files of these comma-separated groups (or file names)
+    #[clap(long, value_delimiter = ',')]
+    only: Vec<String>,
+    /// Do not process files of these comma-separated groups (or file names)
+    #[clap(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print path to configuration file and why it was chosen
+    Path,
+}
+
 fn setup_logger() {
     #[cfg(debug_assertions)]
     let level = LevelFilter::Debug;
@@ -63,40 +128,91 @@ fn main() {
 
     let args = Args::parse();
 
-    let config = match fs::read_to_string(expand_tilde(&args.config)) {
-        Ok(c) => c,
-        Err(_) => {
-            log::error!(
-                "Failed to read Themer configuration file in '{}'",
-                args.config
-            );
+    if let Some(Commands::Init { detect, force, yes }) = args.command {
+        let result = discovery::init_path(args.config.as_deref())
+            .and_then(|target| init::run(&target, detect, force, yes));
+
+        if let Err(e) = result {
+            log::error!("{e}");
+            exit(1);
+        }
+        return;
+    }
+
+    let location = match discovery::discover(args.config.as_deref()) {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("{e}");
             exit(1);
         }
     };
 
-    let config: Config = match serde_yaml::from_str(&config) {
+    let command = args.command.unwrap_or(Commands::Themes { preview: false });
+
+    // Config subcommands should work even if configuration file is broken
+    if let Commands::Config { command } = &command {
+        match command {
+            ConfigCommands::Path => {
+                println!("{} ({})", location.path.display(), location.reason)
+            }
+        }
+        return;
+    }
+
+    let mut config = match loader::load(&location.path, args.config_format) {
         Ok(c) => c,
         Err(e) => {
-            log::error!("Failed to parse configuration file:\n\t{e}");
-            exit(1)
+            log::error!("{e}");
+            exit(1);
         }
     };
+    if let Err(e) = profiles::apply(&mut config, &args.profile) {
+        log::error!("{e}");
+        exit(1);
+    }
     log::debug!("{config:#?}");
 
-    let command = args.command.unwrap_or(Commands::Themes);
-
     match command {
-        Commands::Themes => {
-            println!("{}", "Avaliable themes:".purple());
-            config
-                .themes
-                .into_iter()
-                .for_each(|x| println!("  - {}", x.0));
+        Commands::Themes { preview } => {
+            if preview {
+                config.all_vars().iter().for_each(|(name, vars)| {
+                    preview::preview(name, vars);
+                    println!();
+                });
+            } else {
+                println!("{}", "Avaliable themes:".purple());
+                config
+                    .themes
+                    .into_iter()
+                    .for_each(|x| println!("  - {}", x.0));
+            }
         }
-        Commands::Files { check } => {
+        Commands::Preview { theme } => match config.vars(&theme) {
+            Some(vars) => preview::preview(&theme, &vars),
+            None => {
+                log::error!("Theme `{theme}` is not listed in configuration file.");
+                exit(1);
+            }
+        },
+        Commands::Check => {
+            if !check::run(&config) {
+                exit(1);
+            }
+        }
+        Commands::Lint => {
+            if !lint::run(&config) {
+                exit(1);
+            }
+        }
+        Commands::Files { check, filter } => {
+            config.select_files(&filter.only, &filter.exclude);
             utils::list_files(config, check);
         }
-        Commands::Set { theme } => {
+        Commands::Config { .. } | Commands::Init { .. } => {
+            unreachable!("handled before loading configuration")
+        }
+        Commands::Set { theme, filter } => {
+            config.select_files(&filter.only, &filter.exclude);
             updates::run(theme, &config);
             if let Some(reload_cmd) = config.reload {
                 println!("{}", "Running reload command...".blue());
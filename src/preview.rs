@@ -0,0 +1,123 @@
+use crate::{color::Color, config::ThemeVars};
+use colored::Colorize;
+use std::env;
+
+const RESET: &str = "\x1b[0m";
+
+/// Checks whether the terminal advertises 24-bit color support
+fn supports_truecolor() -> bool {
+    matches!(
+        env::var("COLORTERM").as_deref(),
+        Ok("truecolor") | Ok("24bit")
+    )
+}
+
+/// Produces ANSI escape sequence that sets foreground (`fg = true`) or background color
+fn escape(color: Color, fg: bool, truecolor: bool) -> String {
+    let layer = if fg { 38 } else { 48 };
+
+    if truecolor {
+        format!("\x1b[{layer};2;{};{};{}m", color.r, color.g, color.b)
+    } else {
+        format!("\x1b[{layer};5;{}m", color.to_ansi256())
+    }
+}
+
+/// Prints every color-valued variable of the theme as a swatch
+pub fn preview(name: &str, vars: &ThemeVars) {
+    let truecolor = supports_truecolor();
+
+    println!("{} {}", "Theme".purple(), name.blue());
+    if !truecolor {
+        println!(
+            " {} Terminal does not report truecolor support, colors are approximated",
+            "?".blue()
+        );
+    }
+
+    print!("{}", swatches(vars, truecolor));
+}
+
+/// Lines with a swatch of every color variable, followed by a sample of text when the theme
+/// has both `foreground` and `background`
+fn swatches(vars: &ThemeVars, truecolor: bool) -> String {
+    let colors: Vec<(&String, Color)> = vars
+        .iter()
+        .filter_map(|(k, v)| Color::parse(v).map(|c| (k, c)))
+        .collect();
+
+    if colors.is_empty() {
+        return String::from("  No color variables found\n");
+    }
+
+    let mut lines = String::new();
+    let width = colors.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    for (key, color) in &colors {
+        lines.push_str(&format!(
+            "  {}      {RESET} {key:<width$} {}\n",
+            escape(*color, false, truecolor),
+            color.to_hex()
+        ));
+    }
+
+    let fg = vars.get("foreground").and_then(|v| Color::parse(v));
+    let bg = vars.get("background").and_then(|v| Color::parse(v));
+    if let (Some(fg), Some(bg)) = (fg, bg) {
+        lines.push_str(&format!(
+            "\n  {}{} The quick brown fox jumps over the lazy dog {RESET}\n",
+            escape(fg, true, truecolor),
+            escape(bg, false, truecolor)
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, swatches};
+    use crate::{color::Color, config::ThemeVars};
+
+    fn vars(pairs: &[(&str, &str)]) -> ThemeVars {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn escapes() {
+        let color = Color::parse("#ff8000").unwrap();
+
+        assert_eq!(escape(color, true, true), "\x1b[38;2;255;128;0m");
+        assert_eq!(
+            escape(color, false, false),
+            format!("\x1b[48;5;{}m", color.to_ansi256())
+        );
+    }
+
+    #[test]
+    fn swatch_lines() {
+        let theme = vars(&[
+            ("background", "#000000"),
+            ("foreground", "#ffffff"),
+            ("font", "Mono"),
+            ("bg_alt", "#111"),
+        ]);
+
+        assert_eq!(
+            swatches(&theme, true),
+            "  \x1b[48;2;0;0;0m      \x1b[0m background #000000\n\
+             \x20 \x1b[48;2;255;255;255m      \x1b[0m foreground #ffffff\n\
+             \x20 \x1b[48;2;17;17;17m      \x1b[0m bg_alt     #111111\n\
+             \n  \x1b[38;2;255;255;255m\x1b[48;2;0;0;0m The quick brown fox jumps over the lazy dog \x1b[0m\n"
+        );
+
+        // Sample of text needs both colors
+        assert!(!swatches(&vars(&[("background", "#000")]), true).contains("quick brown fox"));
+        assert_eq!(
+            swatches(&vars(&[("font", "Mono")]), false),
+            "  No color variables found\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{
+    config::{Config, FileConfig, Overrides, Theme, ThemeValue},
+    utils::hostname,
+};
+use std::collections::BTreeMap;
+
+/// Applies overrides of the current host and then of every selected profile in the given order,
+/// so later profiles win
+pub fn apply(config: &mut Config, profiles: &[String]) -> Result<(), String> {
+    let mut sections: Vec<Overrides> = vec![];
+
+    if let Some(host) = hostname() {
+        log::debug!("Hostname: {host}");
+        if let Some(o) = config.hosts.get(&host) {
+            sections.push(o.clone());
+        }
+    }
+
+    for name in profiles {
+        match config.profiles.get(name) {
+            Some(o) => sections.push(o.clone()),
+            None => {
+                return Err(format!(
+                    "Profile `{name}` is not listed in configuration file"
+                ))
+            }
+        }
+    }
+
+    let mut disabled = BTreeMap::new();
+    let base = Overrides {
+        disable: std::mem::take(&mut config.disable),
+        ..Default::default()
+    };
+    apply_overrides(config, &mut disabled, base);
+    for overrides in sections {
+        apply_overrides(config, &mut disabled, overrides);
+    }
+
+    Ok(())
+}
+
+fn apply_overrides(
+    config: &mut Config,
+    disabled: &mut BTreeMap<String, FileConfig>,
+    overrides: Overrides,
+) {
+    for name in overrides.disable {
+        match config.files.remove(&name) {
+            Some(file) => {
+                disabled.insert(name, file);
+            }
+            None => log::warn!("Failed to disable file `{name}`: it does not exist"),
+        }
+    }
+    for name in overrides.enable {
+        if let Some(file) = disabled.remove(&name) {
+            config.files.insert(name, file);
+        }
+    }
+
+    for (name, file) in overrides.files {
+        disabled.remove(&name);
+        config.files.insert(name, file);
+    }
+
+    for theme in config.themes.values_mut() {
+        merge(theme, &overrides.vars);
+    }
+    for (name, vars) in overrides.themes {
+        match config.themes.get_mut(&name) {
+            Some(theme) => merge(theme, &vars),
+            None => log::warn!("Failed to override theme `{name}`: it does not exist"),
+        }
+    }
+}
+
+/// Sets variables of `overrides` in the theme, keeping other variables of nested groups
+fn merge(theme: &mut Theme, overrides: &Theme) {
+    for (key, value) in overrides {
+        match (theme.get_mut(key), value) {
+            (Some(ThemeValue::Group(group)), ThemeValue::Group(nested)) => merge(group, nested),
+            _ => {
+                theme.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply;
+    use crate::loader::load;
+    use std::path::Path;
+
+    #[test]
+    fn profiles() {
+        let mut conf = load(Path::new("./test-configs/profiles.yml"), None).unwrap();
+        apply(&mut conf, &["laptop".to_owned(), "big".to_owned()]).unwrap();
+
+        assert_eq!(
+            conf.files.keys().collect::<Vec<_>>(),
+            vec!["terminal", "waybar"]
+        );
+
+        let vars = conf.vars("dark").unwrap();
+        assert_eq!(vars.get("font.size").unwrap(), "14");
+        assert_eq!(vars.get("font.family").unwrap(), "monospace");
+        assert_eq!(vars.get("background").unwrap(), "#111111");
+        assert_eq!(conf.vars("light").unwrap().get("font.size").unwrap(), "14");
+
+        let mut conf = load(Path::new("./test-configs/profiles.yml"), None).unwrap();
+        apply(&mut conf, &["notify".to_owned()]).unwrap();
+        assert_eq!(
+            conf.files.keys().collect::<Vec<_>>(),
+            vec!["dunst", "polybar", "terminal"]
+        );
+
+        let err = apply(&mut conf, &["missing".to_owned()]).unwrap_err();
+        assert!(err.contains("`missing`"));
+    }
+}
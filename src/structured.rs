@@ -0,0 +1,682 @@
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::Path};
+use toml_edit::{Document, Item, Value as TomlValue};
+
+/// Syntax of files whose values are changed in place with `set`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Syntax {
+    Json,
+    Toml,
+    Yaml,
+    Ini,
+}
+
+impl Syntax {
+    /// Detects syntax by file extension
+    pub fn detect(path: &str) -> Option<Self> {
+        match Path::new(path).extension()?.to_str()? {
+            "json" | "jsonc" => Some(Syntax::Json),
+            "toml" => Some(Syntax::Toml),
+            "yml" | "yaml" => Some(Syntax::Yaml),
+            "ini" | "cfg" => Some(Syntax::Ini),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces value of the `key`, leaving the rest of the file untouched.
+///
+/// Nested keys are separated with dots. Keys that contain dots themselves (`workbench.colorTheme`
+/// in VSCode settings) are matched too, whole name is preferred over nested one. New value keeps
+/// the type of the old one where possible: quoted values stay quoted, numbers and booleans are
+/// written as is
+pub fn set(contents: &str, syntax: Syntax, key: &str, value: &str) -> Result<String, String> {
+    let (span, literal): (_, fn(&str, &str) -> String) = match syntax {
+        Syntax::Json => (find_json(contents, key)?, json_literal),
+        Syntax::Yaml => (find_yaml(contents, key), yaml_literal),
+        Syntax::Ini => (find_ini(contents, key), ini_literal),
+        Syntax::Toml => return set_toml(contents, key, value),
+    };
+
+    let span = span.ok_or(format!("Value of `{key}` is not found"))?;
+    let mut updated = contents.to_owned();
+    updated.replace_range(span.clone(), &literal(&contents[span], value));
+
+    Ok(updated)
+}
+
+/// Rest of the key path after the `name`, empty if the name matches the whole path
+fn strip_key<'a>(path: &'a str, name: &str) -> Option<&'a str> {
+    if path == name {
+        return Some("");
+    }
+
+    path.strip_prefix(name)?.strip_prefix('.')
+}
+
+/// Lines of the text with their offsets, without line breaks
+fn lines(contents: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+
+    contents
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = offset;
+            offset += line.len();
+            (start, line.trim_end_matches(['\n', '\r']))
+        })
+        .collect()
+}
+
+/// Writes the value as JSON string, unless the old value and the new one are not strings
+fn json_literal(old: &str, value: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(v) if !old.starts_with('"') && !v.is_object() && !v.is_array() => value.to_owned(),
+        _ => serde_json::Value::from(value).to_string(),
+    }
+}
+
+fn yaml_literal(old: &str, value: &str) -> String {
+    // Strings must not turn into booleans, numbers or nulls
+    let string = old.is_empty() || !yaml_typed(old);
+    let needs_quotes = value.is_empty()
+        || value != value.trim()
+        || value.contains(['\n', '\r', '\t'])
+        || value.starts_with(['#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`'])
+        || value.starts_with(['{', '[', ',', '?', ':'])
+        || value.starts_with("- ")
+        || value.contains(": ")
+        || value.ends_with(':')
+        || value.contains(" #")
+        || (string && yaml_typed(value));
+
+    // Line breaks are folded inside single quotes
+    if old.starts_with('\'') && !value.contains(['\n', '\r']) {
+        format!("'{}'", value.replace('\'', "''"))
+    } else if old.starts_with('"') || needs_quotes {
+        // JSON string is a valid double-quoted YAML scalar
+        serde_json::Value::from(value).to_string()
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Plain scalars that are read as something else than strings, YAML 1.1 booleans included
+fn yaml_typed(scalar: &str) -> bool {
+    const WORDS: &[&str] = &[
+        "y", "n", "yes", "no", "on", "off", "true", "false", "null", "~", ".inf", "-.inf", "+.inf",
+        ".nan",
+    ];
+    let number = scalar.replace('_', "");
+
+    WORDS.contains(&scalar.to_lowercase().as_str())
+        || number.parse::<f64>().is_ok()
+        || ["0x", "0o", "0b"].iter().any(|prefix| {
+            number
+                .strip_prefix(prefix)
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_hexdigit()))
+        })
+}
+
+/// Quotes the value if the old one was quoted, or if it would be read differently otherwise
+fn ini_literal(old: &str, value: &str) -> String {
+    let quoted = old.len() > 1 && old.starts_with('"') && old.ends_with('"');
+    let needs_quotes = value != value.trim()
+        || value.starts_with(['"', ';', '#'])
+        || ini_value(value) != value.len();
+
+    match quoted || needs_quotes {
+        true => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => value.to_owned(),
+    }
+}
+
+fn set_toml(contents: &str, key: &str, value: &str) -> Result<String, String> {
+    let mut doc: Document = contents.parse().map_err(|e| format!("Invalid TOML: {e}"))?;
+    let names = toml_path(doc.as_item(), key).ok_or(format!("Value of `{key}` is not found"))?;
+
+    let mut item = doc.as_item_mut();
+    for name in &names {
+        item = &mut item[name.as_str()];
+    }
+    let old = match item.as_value_mut() {
+        Some(v) => v,
+        None => return Err(format!("Value of `{key}` is not found")),
+    };
+
+    let mut new = match old {
+        TomlValue::String(_) => TomlValue::from(value),
+        _ => value
+            .parse::<TomlValue>()
+            .ok()
+            .filter(|v| !v.is_array() && !v.is_inline_table())
+            .unwrap_or_else(|| TomlValue::from(value)),
+    };
+    *new.decor_mut() = old.decor().clone();
+    *old = new;
+
+    Ok(doc.to_string())
+}
+
+/// Names of tables leading to the value of the key, the longest matching name wins
+fn toml_path(item: &Item, path: &str) -> Option<Vec<String>> {
+    let mut found: Option<Vec<String>> = None;
+
+    for (name, child) in item.as_table_like()?.iter() {
+        if found.as_ref().is_some_and(|f| f[0].len() >= name.len()) {
+            continue;
+        }
+
+        match strip_key(path, name) {
+            Some("")
+                if child
+                    .as_value()
+                    .is_some_and(|v| !v.is_array() && !v.is_inline_table()) =>
+            {
+                found = Some(vec![name.to_owned()]);
+            }
+            Some(rest) if !rest.is_empty() => {
+                if let Some(mut names) = toml_path(child, rest) {
+                    names.insert(0, name.to_owned());
+                    found = Some(names);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    found
+}
+
+fn find_json(contents: &str, key: &str) -> Result<Option<Range<usize>>, String> {
+    let mut json = Json {
+        src: contents.as_bytes(),
+        pos: 0,
+    };
+
+    json.find(key)
+}
+
+/// Minimal JSON reader that knows positions of values. Comments and trailing commas are allowed,
+/// since they are common in settings files
+struct Json<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Json<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn error(&self, msg: &str) -> String {
+        let line = self.src[..self.pos.min(self.src.len())]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1;
+
+        format!("Invalid JSON at line {line}: {msg}")
+    }
+
+    /// Skips whitespace and comments
+    fn skip(&mut self) {
+        loop {
+            let rest = &self.src[self.pos..];
+            if rest.first().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            } else if rest.starts_with(b"//") {
+                self.pos += rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            } else if rest.starts_with(b"/*") {
+                self.pos += rest
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map_or(rest.len(), |p| p + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut bytes = vec![];
+        self.pos += 1;
+
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c @ (b'"' | b'\\' | b'/')) => bytes.push(c),
+                        Some(c) => bytes.extend([b'\\', c]),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+                Some(c) => bytes.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Skips the value and returns its position
+    fn value(&mut self) -> Result<Range<usize>, String> {
+        self.skip();
+        let start = self.pos;
+
+        match self.peek() {
+            Some(b'"') => {
+                self.string()?;
+            }
+            Some(open @ (b'{' | b'[')) => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.pos += 1;
+
+                loop {
+                    self.skip();
+                    match self.peek() {
+                        Some(c) if c == close => break,
+                        Some(b',' | b':') => self.pos += 1,
+                        Some(_) => {
+                            self.value()?;
+                        }
+                        None => return Err(self.error("unexpected end of file")),
+                    }
+                }
+                self.pos += 1;
+            }
+            Some(_) => {
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || b"+-.".contains(&c))
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.error("unexpected character"));
+                }
+            }
+            None => return Err(self.error("unexpected end of file")),
+        }
+
+        Ok(start..self.pos)
+    }
+
+    /// Reads the value and returns position of the value of the key inside it, the longest
+    /// matching name wins
+    fn find(&mut self, path: &str) -> Result<Option<Range<usize>>, String> {
+        self.skip();
+        if self.peek() != Some(b'{') {
+            self.value()?;
+            return Ok(None);
+        }
+        self.pos += 1;
+
+        // Length of the matched name and position of the value
+        let mut found: Option<(usize, Range<usize>)> = None;
+        loop {
+            self.skip();
+            match self.peek() {
+                Some(b'}') => break,
+                Some(b'"') => (),
+                _ => return Err(self.error("expected key")),
+            }
+
+            let name = self.string()?;
+            self.skip();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected `:`"));
+            }
+            self.pos += 1;
+            self.skip();
+
+            let longer = found.as_ref().is_none_or(|(len, _)| name.len() > *len);
+            match strip_key(path, &name) {
+                Some("") if longer && !matches!(self.peek(), Some(b'{' | b'[')) => {
+                    found = Some((name.len(), self.value()?));
+                }
+                Some(rest) if longer && !rest.is_empty() => {
+                    if let Some(span) = self.find(rest)? {
+                        found = Some((name.len(), span));
+                    }
+                }
+                _ => {
+                    self.value()?;
+                }
+            }
+
+            self.skip();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => break,
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+        self.pos += 1;
+
+        Ok(found.map(|(_, span)| span))
+    }
+}
+
+/// Finds value in block mappings, flow style collections are not supported
+fn find_yaml(contents: &str, key: &str) -> Option<Range<usize>> {
+    yaml_mapping(&lines(contents), key)
+}
+
+fn yaml_mapping(lines: &[(usize, &str)], path: &str) -> Option<Range<usize>> {
+    let significant = |line: &str| {
+        let trimmed = line.trim_start();
+        !trimmed.is_empty() && !trimmed.starts_with('#') && trimmed != "---"
+    };
+    let indent = |line: &str| line.len() - line.trim_start().len();
+
+    // Keys of the mapping have the same indentation as the first one
+    let level = indent(lines.iter().find(|(_, l)| significant(l))?.1);
+    // Length of the matched name and position of the value, the longest name wins
+    let mut found: Option<(usize, Range<usize>)> = None;
+
+    for (i, (offset, line)) in lines.iter().enumerate() {
+        if !significant(line) || indent(line) != level {
+            continue;
+        }
+        let (name, value) = match yaml_key(&line[level..]) {
+            Some(k) => k,
+            None => continue,
+        };
+
+        if found.as_ref().is_some_and(|(len, _)| *len >= name.len()) {
+            continue;
+        }
+
+        match strip_key(path, &name) {
+            Some("") => {
+                if let Some(span) = yaml_scalar(&line[level + value..]) {
+                    let start = offset + level + value;
+                    found = Some((name.len(), start + span.start..start + span.end));
+                }
+            }
+            Some(rest) => {
+                let end = lines[i + 1..]
+                    .iter()
+                    .position(|(_, l)| significant(l) && indent(l) <= level)
+                    .map_or(lines.len(), |p| i + 1 + p);
+
+                if let Some(span) = yaml_mapping(&lines[i + 1..end], rest) {
+                    found = Some((name.len(), span));
+                }
+            }
+            None => (),
+        }
+    }
+
+    found.map(|(_, span)| span)
+}
+
+/// Name of the key and position right after the colon
+fn yaml_key(line: &str) -> Option<(String, usize)> {
+    let (name, rest) = match line.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = line[1..].find(quote)? + 1;
+            (line[1..end].to_owned(), end + 1)
+        }
+        '-' if line.starts_with("- ") || line == "-" => return None,
+        _ => {
+            let colon = line
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| line[i + 1..].is_empty() || line[i + 1..].starts_with(' '))?;
+            (line[..colon].trim_end().to_owned(), colon)
+        }
+    };
+
+    let colon = rest + line[rest..].len() - line[rest..].trim_start().len();
+    match line[colon..].starts_with(':') {
+        true => Some((name, colon + 1)),
+        false => None,
+    }
+}
+
+/// Position of a single line scalar, nested collections and block scalars have no position
+fn yaml_scalar(value: &str) -> Option<Range<usize>> {
+    let start = value.len() - value.trim_start().len();
+    let scalar = &value[start..];
+
+    let len = match scalar.chars().next()? {
+        '#' | '|' | '>' | '{' | '[' | '&' | '*' => return None,
+        '"' => {
+            let mut escaped = false;
+            let end = scalar[1..].find(|c| {
+                let closing = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                closing
+            })?;
+            end + 2
+        }
+        '\'' => {
+            let mut chars = scalar.char_indices().skip(1).peekable();
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                // Quote is escaped by doubling it
+                if c == '\'' && chars.next_if(|(_, c)| *c == '\'').is_none() {
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            end?
+        }
+        _ => scalar.find(" #").unwrap_or(scalar.len()),
+    };
+
+    Some(start..start + scalar[..len].trim_end().len())
+}
+
+/// Finds value in `section.key` or in `key` before the first section
+fn find_ini(contents: &str, key: &str) -> Option<Range<usize>> {
+    let mut section = String::new();
+
+    for (offset, line) in lines(contents) {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            section = trimmed[1..trimmed.len() - 1].trim().to_owned();
+            continue;
+        }
+        if trimmed.starts_with(['#', ';']) {
+            continue;
+        }
+
+        let separator = match trimmed.find(['=', ':']) {
+            Some(s) => s,
+            None => continue,
+        };
+        let name = trimmed[..separator].trim();
+        let full = match section.is_empty() {
+            true => name.to_owned(),
+            false => format!("{section}.{name}"),
+        };
+        if full != key {
+            continue;
+        }
+
+        let value = &trimmed[separator + 1..];
+        let start = offset + (line.len() - line.trim_start().len()) + separator + 1;
+        let start = start + value.len() - value.trim_start().len();
+        return Some(start..start + ini_value(value.trim_start()));
+    }
+
+    None
+}
+
+/// Length of the value without inline comment
+fn ini_value(value: &str) -> usize {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut escaped = false;
+        if let Some(end) = quoted.find(|c| {
+            let closing = c == '"' && !escaped;
+            escaped = c == '\\' && !escaped;
+            closing
+        }) {
+            return end + 2;
+        }
+    }
+
+    // Comment starts with `;` or `#` after whitespace
+    let end = value
+        .char_indices()
+        .find(|&(i, c)| (c == ';' || c == '#') && value[..i].ends_with(char::is_whitespace))
+        .map_or(value.len(), |(i, _)| i);
+
+    value[..end].trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set, Syntax};
+
+    #[test]
+    fn json() {
+        let contents = r##"{
+  // Comments are kept
+  "workbench.colorTheme": "Default Dark+",
+  "editor": { "fontSize": 12, "theme": "x", },
+  "list": [1, 2],
+}"##;
+        let updated = set(contents, Syntax::Json, "workbench.colorTheme", "Nord \"x\"").unwrap();
+        let updated = set(&updated, Syntax::Json, "editor.fontSize", "14").unwrap();
+        let updated = set(&updated, Syntax::Json, "editor.theme", "#fff").unwrap();
+
+        assert_eq!(
+            updated,
+            r##"{
+  // Comments are kept
+  "workbench.colorTheme": "Nord \"x\"",
+  "editor": { "fontSize": 14, "theme": "#fff", },
+  "list": [1, 2],
+}"##
+        );
+        assert_eq!(
+            set(contents, Syntax::Json, "list", "1").unwrap_err(),
+            "Value of `list` is not found"
+        );
+        assert!(set("{\"a\" 1}", Syntax::Json, "a", "1")
+            .unwrap_err()
+            .starts_with("Invalid JSON at line 1"));
+
+        // Whole name is preferred over nested one wherever it is
+        assert_eq!(
+            set("{\"a\": {\"b\": 1}, \"a.b\": 2}", Syntax::Json, "a.b", "3").unwrap(),
+            "{\"a\": {\"b\": 1}, \"a.b\": 3}"
+        );
+    }
+
+    #[test]
+    fn toml() {
+        let contents = "# colors\n[colors.primary]\nbackground = '#000000' # dark\nsize = 10\n";
+        let updated = set(
+            contents,
+            Syntax::Toml,
+            "colors.primary.background",
+            "#111111",
+        )
+        .unwrap();
+        let updated = set(&updated, Syntax::Toml, "colors.primary.size", "12").unwrap();
+
+        assert_eq!(
+            updated,
+            "# colors\n[colors.primary]\nbackground = \"#111111\" # dark\nsize = 12\n"
+        );
+        assert_eq!(
+            set(
+                "[a]\nb = 1\n\n[other]\n\"a.b\" = 2\n",
+                Syntax::Toml,
+                "a.b",
+                "3"
+            )
+            .unwrap(),
+            "[a]\nb = 3\n\n[other]\n\"a.b\" = 2\n"
+        );
+        assert_eq!(
+            set("a = { b = 1 }\n\"a.b\" = 2\n", Syntax::Toml, "a.b", "3").unwrap(),
+            "a = { b = 1 }\n\"a.b\" = 3\n"
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        let contents =
+            "# theme\ncolors:\n  primary:\n    background: '#000000' # dark\n    size: 10\n  other: x\n";
+        let updated = set(contents, Syntax::Yaml, "colors.primary.background", "#111").unwrap();
+        let updated = set(&updated, Syntax::Yaml, "colors.primary.size", "12").unwrap();
+        let updated = set(&updated, Syntax::Yaml, "colors.other", "#fff").unwrap();
+
+        assert_eq!(
+            updated,
+            "# theme\ncolors:\n  primary:\n    background: '#111' # dark\n    size: 12\n  other: \"#fff\"\n"
+        );
+        assert!(set(contents, Syntax::Yaml, "colors.primary", "x").is_err());
+        assert!(set(contents, Syntax::Yaml, "other", "x").is_err());
+
+        assert_eq!(
+            set("a:\n  b: 1\na.b: 2\n", Syntax::Yaml, "a.b", "3").unwrap(),
+            "a:\n  b: 1\na.b: 3\n"
+        );
+    }
+
+    #[test]
+    fn yaml_quoting() {
+        let quoted = |old: &str, value: &str| {
+            let contents = format!("key: {old}\n");
+            set(&contents, Syntax::Yaml, "key", value)
+                .unwrap()
+                .trim_start_matches("key: ")
+                .trim_end()
+                .to_owned()
+        };
+
+        assert_eq!(quoted("x", "two\nlines"), "\"two\\nlines\"");
+        assert_eq!(quoted("'x'", "two\nlines"), "\"two\\nlines\"");
+        assert_eq!(quoted("x", "a: b"), "\"a: b\"");
+        assert_eq!(quoted("x", "#fff"), "\"#fff\"");
+        assert_eq!(quoted("x", "it's"), "it's");
+        // Strings stay strings
+        for value in [
+            "yes", "No", "on", "OFF", "true", "null", "~", "12", "1.5", "0x1F",
+        ] {
+            assert_eq!(quoted("x", value), format!("\"{value}\""));
+        }
+        // Other values keep their type
+        assert_eq!(quoted("10", "12"), "12");
+        assert_eq!(quoted("true", "false"), "false");
+        assert_eq!(quoted("10", "auto"), "auto");
+    }
+
+    #[test]
+    fn ini() {
+        let contents = "font = mono\n\n[global]\n  frame_color = \"#000\"\n; size = 1\n";
+        let updated = set(contents, Syntax::Ini, "global.frame_color", "#fff").unwrap();
+        let updated = set(&updated, Syntax::Ini, "font", "sans").unwrap();
+
+        assert_eq!(
+            updated,
+            "font = sans\n\n[global]\n  frame_color = \"#fff\"\n; size = 1\n"
+        );
+        assert!(set(contents, Syntax::Ini, "global.size", "2").is_err());
+
+        // Inline comments are kept, quotes are escaped
+        let contents = "[global]\nfont = mono ; main font\ntitle = \"a \\\"b\\\"\" # title\n";
+        let updated = set(contents, Syntax::Ini, "global.font", "sans").unwrap();
+        let updated = set(&updated, Syntax::Ini, "global.title", "say \"hi\"").unwrap();
+        assert_eq!(
+            updated,
+            "[global]\nfont = sans ; main font\ntitle = \"say \\\"hi\\\"\" # title\n"
+        );
+        assert_eq!(
+            set("a = b\n", Syntax::Ini, "a", " c ; d").unwrap(),
+            "a = \" c ; d\"\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+/// Encodings of managed files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Used for files that are not valid UTF-8, every byte is read as a single character
+    Latin1,
+}
+
+/// Contents of a managed file with everything needed to write it back the same way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    /// Contents without byte order mark
+    pub contents: String,
+    pub encoding: Encoding,
+    pub bom: bool,
+    /// Line break used in the file, detected by the first one
+    pub line_ending: &'static str,
+}
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Empty UTF-8 file, used for files that don't exist yet
+impl Default for Text {
+    fn default() -> Self {
+        Self {
+            contents: String::new(),
+            encoding: Encoding::Utf8,
+            bom: false,
+            line_ending: "\n",
+        }
+    }
+}
+
+impl Text {
+    /// Fails if contents don't match the encoding stated by byte order mark
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let (contents, encoding, bom) = if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+            (decode_utf8(rest)?, Encoding::Utf8, true)
+        } else if let Some(rest) = bytes.strip_prefix(UTF16_LE_BOM) {
+            (
+                decode_utf16(rest, u16::from_le_bytes)?,
+                Encoding::Utf16Le,
+                true,
+            )
+        } else if let Some(rest) = bytes.strip_prefix(UTF16_BE_BOM) {
+            (
+                decode_utf16(rest, u16::from_be_bytes)?,
+                Encoding::Utf16Be,
+                true,
+            )
+        } else {
+            match std::str::from_utf8(bytes) {
+                Ok(s) => (s.to_owned(), Encoding::Utf8, false),
+                Err(_) => (
+                    bytes.iter().map(|&b| b as char).collect(),
+                    Encoding::Latin1,
+                    false,
+                ),
+            }
+        };
+
+        let line_ending = match contents.find('\n') {
+            Some(i) if contents[..i].ends_with('\r') => "\r\n",
+            _ => "\n",
+        };
+
+        Ok(Self {
+            contents,
+            encoding,
+            bom,
+            line_ending,
+        })
+    }
+
+    /// Converts line breaks of generated text to the ones used in the file
+    pub fn line_breaks(&self, generated: &str) -> String {
+        generated
+            .replace("\r\n", "\n")
+            .replace('\n', self.line_ending)
+    }
+
+    /// Encodes contents the same way as the original file
+    pub fn encode(&self, contents: &str) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+
+        match self.encoding {
+            Encoding::Utf8 => {
+                if self.bom {
+                    bytes.extend_from_slice(UTF8_BOM);
+                }
+                bytes.extend_from_slice(contents.as_bytes());
+            }
+            Encoding::Utf16Le => {
+                bytes.extend_from_slice(UTF16_LE_BOM);
+                contents
+                    .encode_utf16()
+                    .for_each(|c| bytes.extend_from_slice(&c.to_le_bytes()));
+            }
+            Encoding::Utf16Be => {
+                bytes.extend_from_slice(UTF16_BE_BOM);
+                contents
+                    .encode_utf16()
+                    .for_each(|c| bytes.extend_from_slice(&c.to_be_bytes()));
+            }
+            Encoding::Latin1 => {
+                for c in contents.chars() {
+                    match u8::try_from(c) {
+                        Ok(b) => bytes.push(b),
+                        Err(_) => {
+                            return Err(format!(
+                            "Character `{c}` can't be written to the file, it's not UTF-8 encoded"
+                        ))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+fn decode_utf8(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|e| {
+        format!(
+            "Invalid UTF-8 at byte {}, though the file starts with UTF-8 byte order mark",
+            e.utf8_error().valid_up_to() + UTF8_BOM.len()
+        )
+    })
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(String::from(
+            "Odd number of bytes, though the file starts with UTF-16 byte order mark",
+        ));
+    }
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]])).collect();
+
+    String::from_utf16(&units).map_err(|_| {
+        String::from("Invalid UTF-16, though the file starts with UTF-16 byte order mark")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, Text};
+
+    #[test]
+    fn round_trip() {
+        let crlf = Text::decode(b"\xEF\xBB\xBFa\r\nb\n").unwrap();
+        assert_eq!(crlf.contents, "a\r\nb\n");
+        assert_eq!(
+            (crlf.encoding, crlf.bom, crlf.line_ending),
+            (Encoding::Utf8, true, "\r\n")
+        );
+        assert_eq!(crlf.line_breaks("x\ny\r\nz"), "x\r\ny\r\nz");
+        assert_eq!(crlf.encode("a\r\n").unwrap(), b"\xEF\xBB\xBFa\r\n");
+
+        let utf16 = Text::decode(b"\xFF\xFEa\x00\n\x00").unwrap();
+        assert_eq!(utf16.contents, "a\n");
+        assert_eq!(
+            utf16.encode(&utf16.contents).unwrap(),
+            b"\xFF\xFEa\x00\n\x00"
+        );
+
+        let latin1 = Text::decode(b"caf\xE9\n").unwrap();
+        assert_eq!(
+            (latin1.contents.as_str(), latin1.encoding),
+            ("café\n", Encoding::Latin1)
+        );
+        assert_eq!(latin1.encode("café").unwrap(), b"caf\xE9");
+        assert!(latin1.encode("€").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Text::decode(b"\xEF\xBB\xBFcaf\xE9")
+            .unwrap_err()
+            .starts_with("Invalid UTF-8 at byte 6"));
+        assert!(Text::decode(b"\xFF\xFEa\x00\n").is_err());
+        // Unpaired surrogate
+        assert!(Text::decode(b"\xFF\xFE\x00\xD8a\x00").is_err());
+    }
+}
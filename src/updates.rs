@@ -1,14 +1,16 @@
 use crate::{
-    block::BlockGenerator,
-    config::{BlockConfig, Config, FileConfig},
-    utils::expand_tilde,
+    block::{BlockGenerator, BlockSpan},
+    config::{BlockConfig, Config, Escape, FileConfig, Piece, ReplaceRule, When},
+    structured::{self, Syntax},
+    text::{Encoding, Text},
 };
 use colored::Colorize;
-use std::{fs, process::exit};
+use regex::Captures;
+use std::{fs, io, process::exit};
 
 pub fn run(theme_name: String, config: &Config) {
-    let vars = match config.themes.get(&theme_name) {
-        Some(t) => t.clone(),
+    let vars = match config.vars(&theme_name) {
+        Some(t) => t,
         None => {
             log::error!("Theme `{theme_name}` is not listed in configuration file.");
             println!(
@@ -19,28 +21,57 @@ pub fn run(theme_name: String, config: &Config) {
         }
     };
 
+    let literals = config.literals(&theme_name);
     let block_gen = BlockGenerator::new(
         theme_name,
         &vars,
         FileConfig::Single(BlockConfig::default()),
-    );
+    )
+    .with_literals(literals);
     let mut update_gen = UpdatesGenerator::new(block_gen);
 
-    for (_, conf) in &config.files {
-        let update = update_gen.generate(&conf);
-        write_results(update, &conf);
+    for (name, conf) in &config.files {
+        if let Some(reason) = conf.when().and_then(When::unmet) {
+            log::debug!("Skipping `{name}` ({}): {reason}", conf.get_path());
+            continue;
+        }
+
+        let files = match conf.expand() {
+            Ok(f) => f,
+            Err(e) => {
+                log::error!("File `{name}`: {e}");
+                continue;
+            }
+        };
+
+        for conf in files {
+            if let Some(reason) = conf.when().and_then(|w| w.missing(&conf.get_path())) {
+                log::debug!("Skipping `{name}` ({}): {reason}", conf.get_path());
+                continue;
+            }
+
+            let update = update_gen.generate(&conf);
+            write_results(update, &conf);
+        }
     }
 }
 
-fn write_results(results: Result<String, UpdatesError>, conf: &FileConfig) {
+fn write_results(results: Result<Vec<u8>, UpdatesError>, conf: &FileConfig) {
     let path = &conf.get_path();
     match results {
-        Ok(s) => fs::write(expand_tilde(&path), s.as_bytes()).unwrap(),
+        Ok(bytes) => {
+            if let Err(e) = fs::write(path, bytes) {
+                log::error!("Failed to write file {path}: {e}");
+            }
+        }
         Err(e) => match e {
             UpdatesError::InvalidBlock(message) => {
                 log::error!("{message}")
             }
             UpdatesError::UnableToRead => log::error!("Failed to read file {path}"),
+            UpdatesError::NotFound => log::error!("File {path} does not exist"),
+            UpdatesError::InvalidEncoding(message) => log::error!("File {path}: {message}"),
+            UpdatesError::InvalidValue(message) => log::error!("File {path}: {message}"),
         },
     }
 }
@@ -48,7 +79,10 @@ fn write_results(results: Result<String, UpdatesError>, conf: &FileConfig) {
 #[derive(Debug)]
 pub enum UpdatesError {
     UnableToRead,
+    NotFound,
     InvalidBlock(String),
+    InvalidEncoding(String),
+    InvalidValue(String),
 }
 
 pub struct UpdatesGenerator {
@@ -62,57 +96,374 @@ impl UpdatesGenerator {
         }
     }
 
-    pub fn read_file(&self, path: &String) -> Result<String, UpdatesError> {
-        match fs::read_to_string(expand_tilde(&path)) {
-            Ok(f) => Ok(f),
-            Err(_) => {
-                return Err(UpdatesError::UnableToRead);
+    /// Reads the file at `path`, which is already resolved by `Paths::resolve`
+    pub fn read_file(&self, path: &str) -> Result<Text, UpdatesError> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                let text = Text::decode(&bytes).map_err(UpdatesError::InvalidEncoding)?;
+                if text.encoding == Encoding::Latin1 {
+                    log::warn!("File {path} is not UTF-8 encoded, reading it as Latin-1");
+                }
+                Ok(text)
             }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(UpdatesError::NotFound),
+            Err(_) => Err(UpdatesError::UnableToRead),
         }
     }
 
-    pub fn validate_block(&self, contents: &String) -> Result<(), UpdatesError> {
-        if !self.block_generator.get_re().is_match(contents) {
-            let msg = format!(
-                "No Themer block with tag '{}'",
-                self.block_generator
-                    .config
-                    .tag
-                    .clone()
-                    .unwrap_or(String::from("No Tag"))
-            );
-            return Err(UpdatesError::InvalidBlock(msg));
+    /// Checks that the file can be updated: it has valid Themer block, or every value of `set`
+    /// exists and every pattern of `replace` is valid
+    pub fn validate(&self, contents: &str) -> Result<(), UpdatesError> {
+        let config = &self.block_generator.config;
+        if config.has_markers() {
+            return self.validate_block(contents).map(|_| ());
+        }
+
+        if config.template.is_some() {
+            return Self::read_template(config).map(|_| ());
+        }
+        if !config.set.is_empty() {
+            let syntax = Self::syntax(config)?;
+            for key in config.set.keys() {
+                structured::set(contents, syntax, key, "").map_err(UpdatesError::InvalidValue)?;
+            }
+        }
+        // Patterns that match nothing are fine, the file may be updated already
+        for rule in &config.replace {
+            rule.regex().map_err(UpdatesError::InvalidValue)?;
         }
 
         Ok(())
     }
 
-    pub fn generate(&mut self, config: &FileConfig) -> Result<String, UpdatesError> {
-        let mut contents = self.read_file(&config.get_path())?;
+    fn syntax(config: &BlockConfig) -> Result<Syntax, UpdatesError> {
+        config.syntax().ok_or_else(|| {
+            UpdatesError::InvalidValue(String::from(
+                "Unknown syntax of the file, set it with `syntax` option",
+            ))
+        })
+    }
+
+    /// Finds blocks of the current tag, fails if there are none or markers are broken
+    pub fn validate_block(&self, contents: &str) -> Result<Vec<BlockSpan>, UpdatesError> {
+        let tag = self
+            .block_generator
+            .config
+            .tag
+            .clone()
+            .unwrap_or(String::from("No Tag"));
+
+        let blocks = self
+            .block_generator
+            .find_blocks(contents)
+            .map_err(|e| UpdatesError::InvalidBlock(format!("Block with tag '{tag}': {e}")))?;
+
+        if blocks.is_empty() {
+            let msg = format!("No Themer block with tag '{tag}'");
+            return Err(UpdatesError::InvalidBlock(msg));
+        }
+
+        Ok(blocks)
+    }
+
+    pub fn generate(&mut self, config: &FileConfig) -> Result<Vec<u8>, UpdatesError> {
+        if let FileConfig::Single(single) = config {
+            if single.template.is_some() {
+                return self.render_template(single);
+            }
+        }
+
+        let text = self.read_file(&config.get_path())?;
+        let mut contents = text.contents.clone();
 
         for block in config.flatten() {
-            contents = self.update_block(&mut contents, &block)?;
+            contents = match block.has_markers() {
+                true => self.update_block(&contents, &block, &text)?,
+                false => {
+                    let contents = self.set_values(&contents, &block)?;
+                    self.replace_patterns(&contents, &block, &text)?
+                }
+            };
         }
 
-        Ok(contents)
+        text.encode(&contents)
+            .map_err(UpdatesError::InvalidEncoding)
     }
 
     fn update_block(
         &mut self,
-        contents: &mut String,
+        contents: &str,
         config: &BlockConfig,
+        text: &Text,
     ) -> Result<String, UpdatesError> {
         self.block_generator.config = config.clone();
-        self.validate_block(&contents)?;
+        let mut blocks = self.validate_block(contents)?;
 
-        let mut update = self.block_generator.generate();
-        // Replacing dollar sign to avoid Regex issues
-        update = self.block_generator.wrap(&update).replace("$", "$$");
+        if !config.block.all_occurrences {
+            if blocks.len() > 1 {
+                log::warn!(
+                    "Found {} blocks with tag '{}' in {}, only the first one is updated",
+                    blocks.len(),
+                    config.tag.clone().unwrap_or(String::from("No Tag")),
+                    config.path
+                );
+            }
+            blocks.truncate(1);
+        }
 
-        Ok(self
-            .block_generator
-            .get_re()
-            .replacen(&contents, 1, update)
-            .to_string())
+        let generated = self.block_generator.generate();
+
+        // Replacing from the end keeps ranges of preceding blocks valid
+        let mut updated = contents.to_owned();
+        for block in blocks.iter().rev() {
+            let update = self
+                .block_generator
+                .wrap_indented(&generated, &block.indent);
+            updated.replace_range(block.range.clone(), &text.line_breaks(&update));
+        }
+
+        Ok(updated)
+    }
+
+    /// Replaces values listed in `set`, the file has no Themer blocks in this mode
+    fn set_values(&mut self, contents: &str, config: &BlockConfig) -> Result<String, UpdatesError> {
+        if config.set.is_empty() {
+            return Ok(contents.to_owned());
+        }
+
+        self.block_generator.config = config.clone();
+        // Values are quoted by the syntax of the file, escaping would quote them twice
+        self.block_generator.config.block.escape = Escape::None;
+        let syntax = Self::syntax(config)?;
+
+        let mut updated = contents.to_owned();
+        for (key, template) in &config.set {
+            let value = self.block_generator.custom_block(template.clone(), 0);
+            updated = structured::set(&updated, syntax, key, &value)
+                .map_err(UpdatesError::InvalidValue)?;
+        }
+
+        Ok(updated)
+    }
+
+    /// Rewrites matches of `replace` patterns, applied after `set`. Patterns are matched
+    /// against contents with LF line breaks, so `$` works in CRLF files too
+    fn replace_patterns(
+        &mut self,
+        contents: &str,
+        config: &BlockConfig,
+        text: &Text,
+    ) -> Result<String, UpdatesError> {
+        if config.replace.is_empty() {
+            return Ok(contents.to_owned());
+        }
+        self.block_generator.config = config.clone();
+
+        let crlf = text.line_ending == "\r\n";
+        let mut updated = match crlf {
+            true => contents.replace("\r\n", "\n"),
+            false => contents.to_owned(),
+        };
+        for rule in &config.replace {
+            let re = rule.regex().map_err(UpdatesError::InvalidValue)?;
+            if !re.is_match(&updated) {
+                // Literal patterns stop matching once they've been rewritten
+                log::warn!(
+                    "File {}: pattern `{}` matches nothing",
+                    config.path,
+                    rule.pattern
+                );
+                continue;
+            }
+
+            // Variables are expanded once, captured text of the file is never expanded
+            let pieces: Vec<Piece> = rule
+                .pieces(&re)
+                .into_iter()
+                .map(|piece| match piece {
+                    Piece::Text(text) => Piece::Text(self.block_generator.expand(text, 0)),
+                    group => group,
+                })
+                .collect();
+            updated = re
+                .replace_all(&updated, |caps: &Captures| ReplaceRule::fill(&pieces, caps))
+                .into_owned();
+        }
+
+        Ok(match crlf {
+            true => text.line_breaks(&updated),
+            false => updated,
+        })
+    }
+
+    fn read_template(config: &BlockConfig) -> Result<String, UpdatesError> {
+        let path = config
+            .template_path()
+            .map_err(UpdatesError::InvalidValue)?
+            .unwrap_or_default();
+
+        fs::read_to_string(&path)
+            .map_err(|e| UpdatesError::InvalidValue(format!("Failed to read template {path}: {e}")))
+    }
+
+    /// Renders the whole file from `template`, keeping line endings and encoding of the file
+    /// if it exists already
+    fn render_template(&mut self, config: &BlockConfig) -> Result<Vec<u8>, UpdatesError> {
+        let template = config.template.clone().unwrap_or_default();
+        let contents = Self::read_template(config)?;
+
+        let text = match self.read_file(&config.path.to_string()) {
+            Err(UpdatesError::NotFound) => Text::default(),
+            result => result?,
+        };
+
+        self.block_generator.config = config.clone();
+        let mut rendered = String::new();
+        if config.header {
+            let closing = config
+                .closing_comment
+                .as_ref()
+                .map(|c| format!(" {c}"))
+                .unwrap_or_default();
+            rendered.push_str(&format!(
+                "{} Generated by Themer from {template}, changes will be overwritten{closing}\n",
+                config.comment()
+            ));
+        }
+        rendered.push_str(&self.block_generator.custom_block(contents, 0));
+        rendered.push('\n');
+
+        text.encode(&text.line_breaks(&rendered))
+            .map_err(UpdatesError::InvalidEncoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UpdatesError, UpdatesGenerator};
+    use crate::{
+        block::BlockGenerator,
+        config::{BlockConfig, Config, FileConfig, Paths, ThemeVars},
+        text::Text,
+    };
+    use std::fs;
+
+    fn generate(theme: &str, file: &str) -> Result<String, UpdatesError> {
+        let conf: Config =
+            serde_yaml::from_str(&fs::read_to_string("./test-configs/config.yml").unwrap())
+                .unwrap();
+        let file = conf.files.get(file).unwrap().expand().unwrap().remove(0);
+        let gen = BlockGenerator::new(theme.to_owned(), &conf.vars(theme).unwrap(), file.clone())
+            .with_literals(conf.literals(theme));
+
+        UpdatesGenerator::new(gen)
+            .generate(&file)
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    #[test]
+    fn template() {
+        let rendered = generate("theme", "template").unwrap();
+        assert_eq!(
+            rendered,
+            "/* Generated by Themer from ./test-configs/templates/theme.rasi, changes will be overwritten */\n\
+             * {\n    background: #000000;\n}\n"
+        );
+    }
+
+    #[test]
+    fn template_line_endings() {
+        // Existing file keeps its line endings, template is resolved like a path
+        assert_eq!(
+            generate("theme", "template_crlf").unwrap(),
+            "* {\r\n    background: #000000;\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn resolved_paths() {
+        // Matched file names are not expanded again
+        let dir = std::env::temp_dir().join(format!("themer-paths-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("$HOME.conf"), "# THEMER\n# THEMER_END\n").unwrap();
+
+        let mut config = BlockConfig {
+            path: Paths::Single(format!("{}/*.conf", dir.display())),
+            ..Default::default()
+        };
+        config.block.format = "<key> = <value>".to_owned();
+        let file = FileConfig::Single(config).expand().unwrap().remove(0);
+        assert!(file.get_path().ends_with("/$HOME.conf"));
+
+        let mut vars = ThemeVars::new();
+        vars.insert("bg".to_owned(), "#000000".to_owned());
+        let mut gen =
+            UpdatesGenerator::new(BlockGenerator::new(String::new(), &vars, file.clone()));
+        assert_eq!(
+            gen.generate(&file).unwrap(),
+            b"# THEMER\nbg = #000000\n# THEMER_END\n"
+        );
+    }
+
+    #[test]
+    fn replace() {
+        // Captured text is not expanded, line break at the end of `with` is kept
+        assert_eq!(
+            generate("theme", "replace").unwrap(),
+            "set number\nlet g:bg = \"#000000\" \" was \"<background>\"\ncolorscheme theme\n"
+        );
+        // CRLF file is matched line by line too and keeps its line breaks
+        assert_eq!(
+            generate("theme", "replace_crlf").unwrap(),
+            "set number\r\nlet g:bg = \"#000000\" \" was \"<background>\"\r\ncolorscheme theme\r\n"
+        );
+    }
+
+    #[test]
+    fn escape() {
+        // Strings are quoted even if they look like numbers, numbers and booleans are not
+        assert_eq!(
+            generate("typed", "escape").unwrap(),
+            "-- THEMER\n\
+             M.font = \"JetBrains \\\"Mono\\\"\"\n\
+             M.size = \"12\"\n\
+             M.ui_gap = 8\n\
+             M.ui_bold = true\n\
+             M.title = \"JetBrains \\\"Mono\\\"\" .. \"!\"\n\
+             -- THEMER_END\n"
+        );
+    }
+
+    #[test]
+    fn occurrences() {
+        let mut vars = ThemeVars::new();
+        // Blocks are replaced by position, so nothing in values is treated as a substitution
+        vars.insert("bg".to_owned(), "$1 $$ \\0".to_owned());
+        let file = FileConfig::Single(BlockConfig::default());
+        let mut gen = UpdatesGenerator::new(BlockGenerator::new(String::new(), &vars, file));
+
+        let mut config = BlockConfig::default();
+        config.block.format = "<key> = <value>".to_owned();
+
+        let text =
+            Text::decode(b"# THEMER\nold\n# THEMER_END\nkept\n# THEMER\n# THEMER_END\n").unwrap();
+        let updated = gen.update_block(&text.contents, &config, &text).unwrap();
+        assert_eq!(
+            updated,
+            "# THEMER\nbg = $1 $$ \\0\n# THEMER_END\nkept\n# THEMER\n# THEMER_END\n"
+        );
+
+        config.block.all_occurrences = true;
+        let updated = gen.update_block(&text.contents, &config, &text).unwrap();
+        assert_eq!(
+            updated,
+            "# THEMER\nbg = $1 $$ \\0\n# THEMER_END\nkept\n# THEMER\nbg = $1 $$ \\0\n# THEMER_END\n"
+        );
+
+        let broken = Text::decode(b"# THEMER\n# THEMER\n# THEMER_END\n").unwrap();
+        assert!(matches!(
+            gen.update_block(&broken.contents, &config, &broken),
+            Err(UpdatesError::InvalidBlock(e)) if e.ends_with("Nested marker `THEMER` at line 2 (block is opened at line 1)")
+        ));
     }
 }
@@ -3,25 +3,177 @@ use crate::config::BlockConfig;
 use crate::config::Config;
 use crate::config::FileConfig;
 use crate::config::TaggedConfig;
-use crate::updates::UpdatesGenerator;
+use crate::config::ThemeVars;
+use crate::config::When;
+use crate::text::Text;
+use crate::updates::{UpdatesError, UpdatesGenerator};
 use colored::Colorize;
-use std::collections::BTreeMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::cmp::Ordering;
 use std::env;
+use std::fs;
+use std::process::Command;
 
-pub fn expand_tilde(p: &String) -> String {
-    let mut new = p.clone();
+/// Expands `~`, `~user`, `$VAR`, `${VAR}` and `${VAR:-default}` in the path.
+/// Fails if referenced variable is not set and has no default value
+pub fn expand_path(p: &str) -> Result<String, String> {
+    expand_path_with(p, |name| env::var(name).ok(), home_of)
+}
 
-    if new.starts_with("~") {
-        new = new.replacen("~", &env::var("HOME").unwrap_or(String::new()), 1);
+fn expand_path_with(
+    p: &str,
+    var: impl Fn(&str) -> Option<String>,
+    home_of: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    lazy_static! {
+        static ref VAR_RE: Regex = Regex::new(
+            r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}|([A-Za-z_][A-Za-z0-9_]*))"
+        )
+        .unwrap();
     }
 
-    new
+    let mut expanded = String::new();
+    let mut rest = p;
+
+    if let Some(tilde) = p.strip_prefix('~') {
+        let user_end = tilde.find('/').unwrap_or(tilde.len());
+        let user = &tilde[..user_end];
+
+        let home = if user.is_empty() {
+            var("HOME").ok_or("Failed to expand `~`: $HOME is not set")?
+        } else {
+            home_of(user).ok_or(format!("Failed to expand `~{user}`: no such user"))?
+        };
+
+        expanded.push_str(&home);
+        rest = &tilde[user_end..];
+    }
+
+    let mut last = 0;
+    for caps in VAR_RE.captures_iter(rest) {
+        let whole = caps.get(0).unwrap();
+        let name = caps.get(1).or_else(|| caps.get(3)).unwrap().as_str();
+
+        let value = match (var(name), caps.get(2)) {
+            (Some(v), _) if !v.is_empty() => v,
+            (_, Some(default)) => default.as_str().to_owned(),
+            (Some(v), None) => v,
+            (None, None) => {
+                return Err(format!(
+                    "Failed to expand `{p}`: environment variable `{name}` is not set"
+                ))
+            }
+        };
+
+        expanded.push_str(&rest[last..whole.start()]);
+        expanded.push_str(&value);
+        last = whole.end();
+    }
+    expanded.push_str(&rest[last..]);
+
+    Ok(expanded)
+}
+
+/// Looks up home directory of the user in the system user database, so users from NSS sources
+/// like LDAP are found too
+#[cfg(unix)]
+fn home_of(user: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+
+    let name = CString::new(user).ok()?;
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+
+    loop {
+        // SAFETY: every pointer is valid for the call, strings of `passwd` point into `buf`
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+        let code = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+
+        if code == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if code != 0 || result.is_null() || passwd.pw_dir.is_null() {
+            return None;
+        }
+
+        // SAFETY: `pw_dir` is a NUL-terminated string inside `buf`, which is still alive
+        let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+        return Some(dir.to_string_lossy().into_owned());
+    }
+}
+
+#[cfg(not(unix))]
+fn home_of(_user: &str) -> Option<String> {
+    None
+}
+
+/// Name of the current host
+pub fn hostname() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| {
+            Command::new("hostname")
+                .output()
+                .ok()
+                .and_then(|o| String::from_utf8(o.stdout).ok())
+        })?;
+
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// Compares strings treating sequences of digits as numbers
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut n = String::new();
+                    while let Some(c) = it.next_if(|c| c.is_ascii_digit()) {
+                        n.push(c);
+                    }
+                    n
+                };
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+
+                let ord = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
 }
 
 pub fn list_files(config: Config, check: bool) {
     println!("{}", "Listed configuration files:\n".purple());
 
-    let vars = BTreeMap::new();
+    let vars = ThemeVars::new();
     let block_gen = BlockGenerator::new(
         String::new(),
         &vars,
@@ -29,37 +181,70 @@ pub fn list_files(config: Config, check: bool) {
     );
     let mut updates = UpdatesGenerator::new(block_gen);
 
-    config
-        .files
-        .into_iter()
-        .for_each(|(name, config)| match config {
-            FileConfig::Multi(multi) => {
-                list_mutli(name, multi, &mut updates, check);
+    for (name, config) in config.files {
+        // Conditions besides `exists` are the same for every file of the entry
+        let unmet = match check {
+            true => config.when().and_then(When::unmet),
+            false => None,
+        };
+
+        let files = match config.expand() {
+            Ok(f) => f,
+            Err(e) => {
+                println!(
+                    "{} {name} ({}) [{}]",
+                    "err".red(),
+                    config.get_path(),
+                    e.red()
+                );
+                continue;
             }
-            FileConfig::Single(single) => {
-                updates.block_generator.config = single;
-                println!("{}", list_block(name, &mut updates, check));
+        };
+
+        for file in files {
+            // Skipped files are reported only when checking, otherwise they're listed as usual
+            if check {
+                let reason = unmet
+                    .clone()
+                    .or_else(|| file.when().and_then(|w| w.missing(&file.get_path())));
+                if let Some(reason) = reason {
+                    println!(
+                        "{} {name} ({}) [{}]",
+                        "skip".yellow(),
+                        file.get_path(),
+                        reason.yellow()
+                    );
+                    continue;
+                }
             }
-        });
+
+            match file {
+                FileConfig::Multi(multi) => {
+                    list_mutli(name.clone(), multi, &mut updates, check);
+                }
+                FileConfig::Single(single) => {
+                    updates.block_generator.config = single;
+                    println!("{}", list_block(name.clone(), &mut updates, check));
+                }
+            }
+        }
+    }
 }
 
 fn list_mutli(name: String, multi: TaggedConfig, updates: &mut UpdatesGenerator, check: bool) {
     println!("{} ({}) [Multiple blocks]: ", name.blue(), multi.path);
 
-    multi.blocks.iter().for_each(|(tag, config)| {
-        let config = BlockConfig {
-            tag: Some(tag.to_string()),
-            path: multi.path.clone(),
-            comment: multi.comment.clone(),
-            closing_comment: multi.closing_comment.clone(),
-            block: config.clone(),
-        };
+    FileConfig::Multi(multi)
+        .flatten()
+        .into_iter()
+        .for_each(|config| {
+            let tag = config.tag.clone().unwrap_or_default();
 
-        updates.block_generator.config = config;
-        let out = list_block(tag.clone(), updates, check);
+            updates.block_generator.config = config;
+            let out = list_block(tag, updates, check);
 
-        println!("  {out}");
-    });
+            println!("  {out}");
+        });
 }
 
 fn list_block(name: String, updates: &mut UpdatesGenerator, check: bool) -> String {
@@ -68,21 +253,30 @@ fn list_block(name: String, updates: &mut UpdatesGenerator, check: bool) -> Stri
         String::new()
     } else {
         let mut dp = String::from("(");
-        dp.push_str(&updates.block_generator.config.path);
+        dp.push_str(&updates.block_generator.config.path.to_string());
         dp.push(')');
 
         dp
     };
 
     if check {
-        let mut err: Option<&'static str> = None;
+        let mut err: Option<String> = None;
 
-        if let Ok(c) = updates.read_file(&updates.block_generator.config.path) {
-            if updates.validate_block(&c).is_err() {
-                err = Some("No valid block found");
-            }
-        } else {
-            err = Some("Failed to read file");
+        // Rendered templates are created when missing, so there's nothing to read
+        let contents = match updates.block_generator.config.template {
+            Some(_) => Ok(Text::default()),
+            None => updates.read_file(&updates.block_generator.config.path.to_string()),
+        };
+
+        match contents {
+            Ok(c) => match updates.validate(&c.contents) {
+                Err(UpdatesError::InvalidBlock(e)) | Err(UpdatesError::InvalidValue(e)) => {
+                    err = Some(e)
+                }
+                _ => (),
+            },
+            Err(UpdatesError::InvalidEncoding(e)) => err = Some(e),
+            Err(_) => err = Some("Failed to read file".to_owned()),
         }
 
         let mut status = "ok".green();
@@ -90,10 +284,66 @@ fn list_block(name: String, updates: &mut UpdatesGenerator, check: bool) -> Stri
 
         if let Some(e) = err {
             status = "err".red();
-            err_msg = format!("[{}]", e.to_string().red());
+            err_msg = format!("[{}]", e.red());
         }
 
         return format!("{status} {} {} {err_msg}", name, display_path);
     }
     format!("- {} {}", name, display_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_path_with, home_of, natural_cmp};
+    use std::cmp::Ordering;
+
+    fn expand(p: &str) -> Result<String, String> {
+        expand_path_with(
+            p,
+            |name| match name {
+                "HOME" => Some("/home/me".to_owned()),
+                "XDG_CONFIG_HOME" => Some("/home/me/.config".to_owned()),
+                "EMPTY" => Some(String::new()),
+                _ => None,
+            },
+            |user| (user == "other").then(|| "/home/other".to_owned()),
+        )
+    }
+
+    #[test]
+    fn expand_paths() {
+        assert_eq!(expand("~/x").unwrap(), "/home/me/x");
+        assert_eq!(expand("~other/x").unwrap(), "/home/other/x");
+        assert_eq!(
+            expand("$XDG_CONFIG_HOME/kitty/kitty.conf").unwrap(),
+            "/home/me/.config/kitty/kitty.conf"
+        );
+        assert_eq!(expand("${HOME}/x").unwrap(), "/home/me/x");
+        assert_eq!(expand("${MISSING:-/tmp}/x").unwrap(), "/tmp/x");
+        assert_eq!(expand("${EMPTY:-/tmp}/x").unwrap(), "/tmp/x");
+        assert_eq!(expand("/plain/path~").unwrap(), "/plain/path~");
+    }
+
+    #[test]
+    fn expand_errors() {
+        assert!(expand("$MISSING/x")
+            .unwrap_err()
+            .contains("`MISSING` is not set"));
+        assert!(expand("~nobody/x").unwrap_err().contains("no such user"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn user_database() {
+        assert!(home_of("root").is_some_and(|home| home.starts_with('/')));
+        assert_eq!(home_of("themer-surely-missing-user"), None);
+        assert_eq!(home_of("nul\0byte"), None);
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("color2", "color10"), Ordering::Less);
+        assert_eq!(natural_cmp("color10", "color10"), Ordering::Equal);
+        assert_eq!(natural_cmp("b1", "a2"), Ordering::Greater);
+    }
+}
@@ -0,0 +1,2 @@
+-- THEMER
+-- THEMER_END